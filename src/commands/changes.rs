@@ -0,0 +1,78 @@
+// commands/changes.rs
+//
+use crate::{
+    commands::command::Command,
+    db::connect_db,
+    models::{
+        changes::{retrieve_changes, ChangeKind},
+        configs::*,
+    },
+};
+use {async_trait::async_trait, eyre::Result};
+
+pub struct ChangesCommand<'a> {
+    config: &'a Configs,
+    course: Option<i64>,
+    last: usize,
+}
+
+impl<'a> ChangesCommand<'a> {
+    pub fn new(config: &'a Configs, course: Option<i64>, last: usize) -> Self {
+        Self {
+            config,
+            course,
+            last,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Command for ChangesCommand<'a> {
+    async fn execute(&mut self) -> Result<()> {
+        let mut conn = connect_db()?;
+        let changes = retrieve_changes(&mut conn, self.last.max(1), self.course)?;
+        if changes.is_empty() {
+            println!("Nothing changed");
+            return Ok(());
+        }
+
+        let mut group = None;
+        for change in changes {
+            if group != Some((change.changeset, change.courseid)) {
+                group = Some((change.changeset, change.courseid));
+                let course = self
+                    .config
+                    .get_course_name(change.courseid)
+                    .cloned()
+                    .unwrap_or_else(|| change.courseid.to_string());
+                println!(
+                    "{} {}",
+                    change.detectedat.as_deref().unwrap_or_default(),
+                    course
+                );
+            }
+
+            let symbol = match change.kind {
+                ChangeKind::Added => '+',
+                ChangeKind::Removed => '-',
+                ChangeKind::Modified => '~',
+            };
+            match &change.detail {
+                Some(detail) => println!(
+                    "  {} {:<10} {}: {}",
+                    symbol,
+                    change.entity.as_str(),
+                    change.name,
+                    detail
+                ),
+                None => println!(
+                    "  {} {:<10} {}",
+                    symbol,
+                    change.entity.as_str(),
+                    change.name
+                ),
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,11 @@
 // commands/command.rs
 //
 use super::{download::DownloadCommand, fetch::FetchCommand, parse::ParseCommand};
-use crate::{models::configs::Configs, ws::ApiClient};
+use crate::{db::use_database, models::configs::Configs, utils::SyncLock, ws::ApiClient};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use {async_trait::async_trait, eyre::Result};
 
 #[async_trait]
@@ -11,23 +15,68 @@ pub trait Command {
 
 pub struct DefaultCommand<'a> {
     config: &'a Configs,
-    client: ApiClient,
+    /// None when offline, only parsing is done then
+    client: Option<ApiClient>,
+    full: bool,
+    /// Set to stop before the next step, see `WatchCommand`
+    stop: Option<Arc<AtomicBool>>,
 }
 
 impl<'a> DefaultCommand<'a> {
-    pub fn new(config: &'a Configs, client: ApiClient) -> Self {
-        Self { config, client }
+    pub fn new(config: &'a Configs, client: Option<ApiClient>, full: bool) -> Self {
+        Self {
+            config,
+            client,
+            full,
+            stop: None,
+        }
+    }
+
+    pub fn stop_on(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    fn stopping(&self, next: &str) -> bool {
+        let stopping = self
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::SeqCst));
+        if stopping {
+            log::info!("Stopping before {}", next);
+        }
+        stopping
     }
 }
 
 #[async_trait]
 impl<'a> Command for DefaultCommand<'a> {
     async fn execute(&mut self) -> Result<()> {
-        let mut fetch_command = FetchCommand::new(self.client.clone(), self.config);
-        fetch_command.execute().await?;
+        let Some(_lock) = SyncLock::acquire()? else {
+            return Err(eyre::eyre!(
+                "Another sync is running, remove '{}' if it isn't",
+                SyncLock::path().display()
+            ));
+        };
+
+        if self.client.is_some() {
+            let mut fetch_command =
+                FetchCommand::new(self.client.clone(), self.config, self.full, false);
+            fetch_command.execute().await?;
+
+            if self.stopping("downloading") {
+                return Ok(());
+            }
+            let mut download_command =
+                DownloadCommand::new(self.client.clone(), self.config, false);
+            download_command.execute().await?;
+        } else {
+            log::info!("Offline, parsing what is already stored");
+        }
 
-        let mut download_command = DownloadCommand::new(self.client.clone(), self.config);
-        download_command.execute().await?;
+        if self.stopping("parsing") {
+            return Ok(());
+        }
 
         let mut parse_command = ParseCommand::new(self.config);
         parse_command.execute().await?;
@@ -35,3 +84,54 @@ impl<'a> Command for DefaultCommand<'a> {
         Ok(())
     }
 }
+
+/// `DefaultCommand` for the default profile and then every other one. A
+/// profile that fails is logged and the next one still syncs.
+pub struct AllProfilesCommand {
+    full: bool,
+    offline: bool,
+}
+
+impl AllProfilesCommand {
+    pub fn new(full: bool, offline: bool) -> Self {
+        Self { full, offline }
+    }
+
+    async fn sync_profile(&self, name: Option<&str>) -> Result<()> {
+        let mut config = Configs::new()?;
+        config.select_profile(name)?;
+        use_database(config.database_path())?;
+        let client = if self.offline {
+            None
+        } else {
+            config.load_token().await?;
+            Some(ApiClient::from_config(&config)?)
+        };
+        DefaultCommand::new(&config, client, self.full)
+            .execute()
+            .await
+    }
+}
+
+#[async_trait]
+impl Command for AllProfilesCommand {
+    async fn execute(&mut self) -> Result<()> {
+        let names: Vec<String> = Configs::new()?.profiles.into_keys().collect();
+        let mut failed = Vec::new();
+
+        for name in std::iter::once(None).chain(names.iter().map(Some)) {
+            let profile = name.map_or("default", String::as_str);
+            log::info!("Syncing profile {}", profile);
+
+            if let Err(e) = self.sync_profile(name.map(String::as_str)).await {
+                log::error!("Failed to sync profile {}: {:?}", profile, e);
+                failed.push(profile.to_string());
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(eyre::eyre!("Failed to sync {}", failed.join(", ")));
+        }
+        Ok(())
+    }
+}
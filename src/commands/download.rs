@@ -1,34 +1,150 @@
 // commands/download.rs
 //
-use crate:: {
+use crate::{
     commands::command::Command,
     db::connect_db,
-    downloader::save_files,
+    downloader::{
+        archive_removed_files, course_file_path, embedded_file_path, get_course_id,
+        removed_downloads, save_embedded_files, save_files, save_urls, shortcut_path,
+        url_target_path,
+    },
     models::configs::*,
     models::course::get_all_files,
+    models::embedded::retrieve_course_embedded_files,
+    models::urls::retrieve_course_urls,
+    ui::table::print_table,
     ws::*,
 };
 use {async_trait::async_trait, eyre::Result};
 
 pub struct DownloadCommand<'a> {
-    client: ApiClient, // owned ApiClient instance
+    client: Option<ApiClient>, // owned ApiClient instance, none when offline
     config: &'a Configs,
+    /// List what would be downloaded instead of downloading it
+    dry_run: bool,
 }
 
 impl<'a> DownloadCommand<'a> {
-    pub fn new(client: ApiClient, config: &'a Configs) -> Self {
-        Self { client, config }
+    pub fn new(client: Option<ApiClient>, config: &'a Configs, dry_run: bool) -> Self {
+        Self {
+            client,
+            config,
+            dry_run,
+        }
     }
 }
 
 #[async_trait]
 impl<'a> Command for DownloadCommand<'a> {
     async fn execute(&mut self) -> Result<()> {
-        self.client = ApiClient::from_config(&self.config)?;
-        let conn = connect_db();
-        let files = get_all_files(&mut conn.unwrap())?;
-        save_files(&self.client, files, &self.config).await?;
+        if self.dry_run {
+            return print_download_plan(self.config);
+        }
+        let Some(client) = self.client.as_ref() else {
+            return Err(eyre::eyre!(
+                "download needs the network, run it without --offline or with --dry-run"
+            ));
+        };
+
+        if self.config.output.archive_removed {
+            archive_removed_files(self.config)?;
+        }
+
+        let mut conn = connect_db()?;
+        let files = get_all_files(&mut conn)?;
+        save_files(client, files, self.config).await?;
+
+        for course in &self.config.courses {
+            let urls = retrieve_course_urls(&mut conn, course.id)?;
+            save_urls(client, urls, self.config).await?;
+
+            let embedded = retrieve_course_embedded_files(&mut conn, course.id)?;
+            save_embedded_files(client, embedded, self.config).await?;
+        }
 
         Ok(())
     }
 }
+
+// What `execute` would move, download and write, in order. Files already on
+// disk are listed too, whether they changed is only known by asking Moodle.
+fn print_download_plan(config: &Configs) -> Result<()> {
+    let name = |id: i64| {
+        config
+            .get_course_name(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    };
+    let allowed = |url: &str| is_allowed_url(&config.links.allowed_hosts, url);
+    let mut rows = Vec::new();
+
+    if config.output.archive_removed {
+        for removed in removed_downloads(config)? {
+            rows.push([
+                name(removed.course_id),
+                String::from("Archive"),
+                removed.localpath.display().to_string(),
+                removed.archived_path.display().to_string(),
+            ]);
+        }
+    }
+
+    let mut conn = connect_db()?;
+    for file in get_all_files(&mut conn)? {
+        let (Some(filename), Some(fileurl)) = (file.filename, file.fileurl) else {
+            continue;
+        };
+        let Some(course_id) = get_course_id(&filename)? else {
+            continue;
+        };
+        rows.push([
+            name(course_id),
+            String::from("Download"),
+            fileurl,
+            course_file_path(config, course_id, &filename)
+                .display()
+                .to_string(),
+        ]);
+    }
+
+    for course in &config.courses {
+        for url in retrieve_course_urls(&mut conn, course.id)? {
+            rows.push([
+                name(course.id),
+                String::from("Shortcut"),
+                url.externalurl.clone(),
+                shortcut_path(config, &url).display().to_string(),
+            ]);
+            if allowed(&url.externalurl) {
+                rows.push([
+                    name(course.id),
+                    String::from("Download"),
+                    url.externalurl.clone(),
+                    url_target_path(config, &url).display().to_string(),
+                ]);
+            }
+        }
+
+        for file in retrieve_course_embedded_files(&mut conn, course.id)? {
+            if !is_moodle_url(&config.api.base_url, &file.url) && !allowed(&file.url) {
+                continue;
+            }
+            rows.push([
+                name(course.id),
+                String::from("Download"),
+                file.url.clone(),
+                embedded_file_path(config, course.id, &file.url)
+                    .display()
+                    .to_string(),
+            ]);
+        }
+    }
+
+    print_table(
+        ["Course", "Action", "From", "To"],
+        rows,
+        "Nothing to download",
+    );
+    println!("Nothing was downloaded or written");
+    Ok(())
+}
@@ -0,0 +1,95 @@
+// commands/export.rs
+//
+use crate::{
+    commands::command::Command,
+    db::connect_db,
+    export::{
+        epub::export_epub,
+        html::export_site,
+        json::{export_json, export_yaml},
+        load_course, load_courses,
+        overview::{load_overview, overview_json, overview_markdown},
+    },
+    models::configs::*,
+    ui::cli::ExportTarget,
+    utils::data_dir,
+};
+use {
+    async_trait::async_trait,
+    eyre::Result,
+    std::{fs, path::PathBuf},
+};
+
+pub struct ExportCommand<'a> {
+    config: &'a Configs,
+    target: ExportTarget,
+}
+
+impl<'a> ExportCommand<'a> {
+    pub fn new(config: &'a Configs, target: ExportTarget) -> Self {
+        Self { config, target }
+    }
+}
+
+#[async_trait]
+impl<'a> Command for ExportCommand<'a> {
+    async fn execute(&mut self) -> Result<()> {
+        match &self.target {
+            ExportTarget::Html { out } => {
+                let courses = load_courses(self.config)?;
+                let out_dir = out.clone().unwrap_or_else(|| data_dir().join("site"));
+                export_site(&courses, &out_dir)?;
+                println!("Exported site to {}", out_dir.join("index.html").display());
+            }
+            ExportTarget::Epub { course, out } => {
+                let mut conn = connect_db()?;
+                let course = load_course(&mut conn, self.config, *course)?;
+                // Shortnames may hold dots, `with_extension` would cut them off
+                let out_file = out.clone().unwrap_or_else(|| {
+                    let mut path = self.config.get_course_dir(course.id).into_os_string();
+                    path.push(".epub");
+                    PathBuf::from(path)
+                });
+                let language = self.config.output.language.as_deref();
+                export_epub(&course, &out_file, language)?;
+                println!("Exported book to {}", out_file.display());
+            }
+            ExportTarget::Grades { json, out } => {
+                let overview = load_overview(self.config)?;
+                let document = if *json {
+                    overview_json(&overview)?
+                } else {
+                    overview_markdown(&overview)
+                };
+
+                match out {
+                    Some(out_file) => {
+                        fs::write(out_file, document)?;
+                        log::info!("Exported grade overview to {}", out_file.display());
+                    }
+                    None => print!("{}", document),
+                }
+            }
+            ExportTarget::Json { course, yaml, out } => {
+                let courses = match course {
+                    Some(course) => vec![load_course(&mut connect_db()?, self.config, *course)?],
+                    None => load_courses(self.config)?,
+                };
+                let document = if *yaml {
+                    export_yaml(&courses)?
+                } else {
+                    export_json(&courses)?
+                };
+
+                match out {
+                    Some(out_file) => {
+                        fs::write(out_file, document)?;
+                        log::info!("Exported courses to {}", out_file.display());
+                    }
+                    None => print!("{}", document),
+                }
+            }
+        }
+        Ok(())
+    }
+}
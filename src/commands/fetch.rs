@@ -1,27 +1,53 @@
-use crate::models::{assignments::insert_assignments, scorm::insert_scorms};
+use crate::models::{
+    assignments::insert_assignments,
+    changes::begin_changeset,
+    completion::{insert_activity_completion, insert_course_completion},
+    scorm::insert_scorms,
+    urls::insert_urls,
+};
 
 // commands.rs
 //
 use {
     crate::commands::command::Command,
-    crate::db::connect_db,
+    crate::db::{connect_db, db_path},
     crate::models::{
         configs::*,
-        course::{insert_course_sections, Pages},
-        grades::insert_grades,
+        course::{
+            insert_course_modules, insert_course_sections, insert_course_structure,
+            retrieve_last_sync, update_last_sync, Pages,
+        },
+        embedded::insert_embedded_files,
+        grades::{insert_course_totals, insert_grades},
     },
+    crate::notifier::notify,
+    crate::ui::table::print_table,
+    crate::utils::format_date,
     crate::ws::*,
 };
-use {async_trait::async_trait, eyre::Result, rusqlite::Connection};
+use {async_trait::async_trait, chrono::Utc, eyre::Result, rusqlite::Connection};
+
+// Updates are asked for from a little before the last fetch, so a clock
+// behind Moodle's doesn't miss any
+const SYNC_OVERLAP: i64 = 300;
 
 pub struct FetchCommand<'a> {
-    client: ApiClient, // owned ApiClient instance
+    client: Option<ApiClient>, // owned ApiClient instance, none when offline
     config: &'a Configs,
+    /// Refetch every course in full instead of what changed since the last fetch
+    full: bool,
+    /// List the requests instead of making them
+    dry_run: bool,
 }
 
 impl<'a> FetchCommand<'a> {
-    pub fn new(client: ApiClient, config: &'a Configs) -> Self {
-        Self { client, config }
+    pub fn new(client: Option<ApiClient>, config: &'a Configs, full: bool, dry_run: bool) -> Self {
+        Self {
+            client,
+            config,
+            full,
+            dry_run,
+        }
     }
 }
 
@@ -29,37 +55,121 @@ impl<'a> FetchCommand<'a> {
 impl<'a> Command for FetchCommand<'a> {
     async fn execute(&mut self) -> Result<()> {
         let mut conn = connect_db()?;
-        self.client = ApiClient::from_config(&self.config)?;
+        if self.dry_run {
+            return print_fetch_plan(&mut conn, self.config, self.full);
+        }
+        let Some(client) = self.client.as_mut() else {
+            return Err(eyre::eyre!(
+                "fetch needs the network, run it without --offline or with --dry-run"
+            ));
+        };
 
-        let pages = fetch_page_handler(&mut self.client).await?;
-        fetch_course_handler(&mut self.client, &mut conn, &self.config, pages).await?;
-        fetch_assignment_handler(&mut self.client).await?;
-        fetch_grade_handler(&mut self.client, &self.config).await?;
-        fetch_scorm_handler(&mut self.client).await?;
+        let changeset = begin_changeset(&conn)?;
+
+        let pages = fetch_page_handler(client).await?;
+        fetch_course_handler(client, &mut conn, self.config, pages, changeset, self.full).await?;
+        fetch_assignment_handler(client, changeset).await?;
+        fetch_grade_handler(client, self.config, changeset).await?;
+        fetch_grade_overview_handler(client, self.config).await?;
+        fetch_completion_handler(client, self.config).await?;
+        fetch_scorm_handler(client).await?;
+        fetch_url_handler(client).await?;
+
+        if let Err(e) = notify(self.config, changeset).await {
+            log::error!("Failed to send notifications: {:?}", e);
+        }
 
         Ok(())
     }
 }
 
-pub async fn fetch_assignment_handler(client: &ApiClient) -> Result<()> {
+// The requests `execute` would make, in order. How many modules an
+// incremental fetch refetches is only known once Moodle answers.
+fn print_fetch_plan(conn: &mut Connection, config: &Configs, full: bool) -> Result<()> {
+    let all = || String::from("All");
+    let courses: Vec<(i64, String)> = config
+        .courses
+        .iter()
+        .map(|course| {
+            let name = course.shortname.clone();
+            (course.id, name.unwrap_or_else(|| course.id.to_string()))
+        })
+        .collect();
+    let mut rows = vec![[all(), GET_PAGES.to_string(), String::new()]];
+
+    for (id, name) in &courses {
+        let last_sync = if full {
+            None
+        } else {
+            retrieve_last_sync(conn, *id)?
+        };
+        let contents = match last_sync {
+            Some(since) => {
+                rows.push([
+                    name.clone(),
+                    GET_UPDATES.to_string(),
+                    format!("Changes since {}", format_date(since - SYNC_OVERLAP)),
+                ]);
+                "Once per changed module, in full if updates are unavailable"
+            }
+            None if full => "In full",
+            None => "In full, never fetched",
+        };
+        rows.push([name.clone(), GET_CONTENTS.to_string(), contents.to_string()]);
+        if last_sync.is_some() {
+            rows.push([
+                name.clone(),
+                GET_CONTENTS.to_string(),
+                String::from("Outline without files, for removed content"),
+            ]);
+        }
+    }
+
+    rows.push([all(), GET_ASSIGNMENTS.to_string(), String::new()]);
+    for (_, name) in &courses {
+        rows.push([name.clone(), GET_GRADES.to_string(), String::new()]);
+    }
+    rows.push([all(), GET_GRADE_OVERVIEW.to_string(), String::new()]);
+    for (_, name) in &courses {
+        for function in [GET_ACTIVITIES_COMPLETION, GET_COURSE_COMPLETION] {
+            rows.push([name.clone(), function.to_string(), String::new()]);
+        }
+    }
+    rows.push([all(), GET_SCORM.to_string(), String::new()]);
+    rows.push([all(), GET_URLS.to_string(), String::new()]);
+
+    println!("A fetch would request, from {}:", config.api.base_url);
+    print_table(["Course", "Function", "Note"], rows, "Nothing");
+    println!(
+        "Nothing was requested, the results would be stored in {}",
+        db_path().display()
+    );
+    Ok(())
+}
+
+pub async fn fetch_assignment_handler(client: &ApiClient, changeset: i64) -> Result<()> {
     let mut conn = connect_db()?;
     let response = client.fetch_assignments().await?;
     if let ApiResponse::Assignments(assignments) = response {
         log::debug!("{:#?}", assignments);
-        insert_assignments(&mut conn, assignments)?;
+        insert_assignments(&mut conn, assignments, changeset)?;
     } else {
         return Err(eyre::eyre!("Unexpected API response: {:?}", response));
     }
     Ok(())
 }
 
-pub async fn fetch_grade_handler(client: &ApiClient, config: &Configs) -> Result<()> {
+pub async fn fetch_grade_handler(
+    client: &ApiClient,
+    config: &Configs,
+    changeset: i64,
+) -> Result<()> {
     for course in &config.courses {
         let mut conn = connect_db()?;
         let response = client.fetch_course_grades(course.id).await?;
         if let ApiResponse::UserGrades(grades) = response {
             log::debug!("{:#?}", grades);
-            insert_grades(&mut conn, grades.usergrades)?;
+            insert_grades(&mut conn, grades.usergrades, changeset)?;
         } else {
             return Err(eyre::eyre!("Unexpected API response: {:?}", response));
         }
@@ -67,12 +177,71 @@ pub async fn fetch_grade_handler(client: &ApiClient, config: &Configs) -> Result
     Ok(())
 }
 
+pub async fn fetch_grade_overview_handler(client: &ApiClient, config: &Configs) -> Result<()> {
+    let response = client.fetch_grade_overview().await?;
+    match response {
+        ApiResponse::GradeOverview(overview) => {
+            log::debug!("{:#?}", overview);
+            let totals: Vec<_> = overview
+                .grades
+                .into_iter()
+                .filter(|total| {
+                    config
+                        .courses
+                        .iter()
+                        .any(|course| course.id == total.courseid)
+                })
+                .collect();
+            insert_course_totals(&mut connect_db()?, &totals)?;
+        }
+        // Sites can leave the overview report out of the mobile service,
+        // the overview then falls back to the stored grade items
+        ApiResponse::Exception(error) => {
+            log::warn!("Skipping the grade overview: {:?}", error);
+        }
+        _ => return Err(eyre::eyre!("Unexpected API response: {:?}", response)),
+    }
+    Ok(())
+}
+
+pub async fn fetch_completion_handler(client: &ApiClient, config: &Configs) -> Result<()> {
+    for course in &config.courses {
+        let mut conn = connect_db()?;
+        // Courses without completion tracking or criteria answer with an exception
+        match client.fetch_activities_completion(course.id).await? {
+            ApiResponse::ActivitiesCompletion(completion) => {
+                log::debug!("{:#?}", completion);
+                insert_activity_completion(&mut conn, course.id, completion)?;
+            }
+            ApiResponse::Exception(error) => {
+                log::info!(
+                    "No activity completion for course {}: {:?}",
+                    course.id,
+                    error
+                );
+            }
+            response => return Err(eyre::eyre!("Unexpected API response: {:?}", response)),
+        }
+        match client.fetch_course_completion(course.id).await? {
+            ApiResponse::CourseCompletion(completion) => {
+                log::debug!("{:#?}", completion);
+                insert_course_completion(&mut conn, course.id, completion.completionstatus)?;
+            }
+            ApiResponse::Exception(error) => {
+                log::info!("No course completion for course {}: {:?}", course.id, error);
+            }
+            response => return Err(eyre::eyre!("Unexpected API response: {:?}", response)),
+        }
+    }
+    Ok(())
+}
+
 pub async fn fetch_page_handler(client: &ApiClient) -> Result<Pages> {
     let response = client.fetch_course_pages().await?;
     if let ApiResponse::Pages(pages) = response {
         Ok(pages)
     } else {
-        return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+        Err(eyre::eyre!("Unexpected API response: {:?}", response))
     }
 }
 
@@ -81,19 +250,90 @@ pub async fn fetch_course_handler(
     conn: &mut Connection,
     config: &Configs,
     pages: Pages,
+    changeset: i64,
+    full: bool,
 ) -> Result<()> {
     for course in &config.courses {
-        let response = client.fetch_course_contents(course.id).await?;
-        if let ApiResponse::Sections(mut sections) = response {
-            log::debug!("{:#?}", sections);
-            insert_course_sections(conn, &mut sections, &pages, course.id)?;
+        let started = Utc::now().timestamp();
+        let last_sync = if full {
+            None
         } else {
-            return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+            retrieve_last_sync(conn, course.id)?
+        };
+
+        let updated = match last_sync {
+            Some(since) => fetch_updated_modules(client, course.id, since - SYNC_OVERLAP).await?,
+            None => None,
+        };
+        match updated {
+            Some(modules) => {
+                log::info!(
+                    "Course {}: {} modules changed since the last fetch",
+                    course.id,
+                    modules.len()
+                );
+                for cmid in modules {
+                    let response = client.fetch_course_module(course.id, cmid).await?;
+                    if let ApiResponse::Sections(mut sections) = response {
+                        log::debug!("{:#?}", sections);
+                        // Sections without the module come back empty
+                        sections.retain(|section| !section.modules.is_empty());
+                        insert_course_modules(conn, &mut sections, &pages, course.id, changeset)?;
+                        insert_embedded_files(conn, &sections, course.id)?;
+                    } else {
+                        return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+                    }
+                }
+
+                // Updates leave out removed modules and section edits
+                let response = client.fetch_course_outline(course.id).await?;
+                if let ApiResponse::Sections(mut sections) = response {
+                    log::debug!("{:#?}", sections);
+                    insert_course_structure(conn, &mut sections, &pages, course.id, changeset)?;
+                } else {
+                    return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+                }
+            }
+            None => {
+                let response = client.fetch_course_contents(course.id).await?;
+                if let ApiResponse::Sections(mut sections) = response {
+                    log::debug!("{:#?}", sections);
+                    insert_course_sections(conn, &mut sections, &pages, course.id, changeset)?;
+                    insert_embedded_files(conn, &sections, course.id)?;
+                } else {
+                    return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+                }
+            }
         }
+        update_last_sync(conn, course.id, started)?;
     }
     Ok(())
 }
 
+// Modules of the course changed since `since`, none when the site can't tell
+// and the course has to be fetched in full
+async fn fetch_updated_modules(
+    client: &ApiClient,
+    courseid: i64,
+    since: i64,
+) -> Result<Option<Vec<i64>>> {
+    match client.fetch_course_updates(courseid, since).await? {
+        ApiResponse::Updates(updates) => {
+            log::debug!("{:#?}", updates);
+            Ok(Some(updates.modules()))
+        }
+        ApiResponse::Exception(error) => {
+            log::warn!(
+                "Fetching course {} in full, updates are unavailable: {:?}",
+                courseid,
+                error
+            );
+            Ok(None)
+        }
+        response => Err(eyre::eyre!("Unexpected API response: {:?}", response)),
+    }
+}
+
 pub async fn fetch_scorm_handler(client: &ApiClient) -> Result<()> {
     let response = client.fetch_scorms().await?;
     if let ApiResponse::Scorms(scorms) = response {
@@ -104,3 +344,14 @@ pub async fn fetch_scorm_handler(client: &ApiClient) -> Result<()> {
     }
     Ok(())
 }
+
+pub async fn fetch_url_handler(client: &ApiClient) -> Result<()> {
+    let response = client.fetch_urls().await?;
+    if let ApiResponse::Urls(urls) = response {
+        log::debug!("{:#?}", urls);
+        insert_urls(&mut connect_db()?, urls)?;
+    } else {
+        return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+    }
+    Ok(())
+}
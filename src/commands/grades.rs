@@ -0,0 +1,200 @@
+// commands/grades.rs
+//
+use crate::{
+    commands::command::Command,
+    db::connect_db,
+    export::overview::{load_overview, overview_rows, OVERVIEW_HEADER},
+    models::{
+        configs::*,
+        grades::{
+            format_grade, projected_percentage, retrieve_course_grades, retrieve_grade_history,
+            GradeHistory, GradeItem,
+        },
+    },
+    parser::format_percentage,
+    ui::table::print_table,
+    utils::{format_date, plain_text},
+};
+use {
+    async_trait::async_trait,
+    eyre::Result,
+    std::{
+        collections::HashMap,
+        io::{self, Write},
+    },
+};
+
+pub struct GradesCommand<'a> {
+    config: &'a Configs,
+    course: Option<i64>,
+    history: bool,
+    what_if: bool,
+    overview: bool,
+}
+
+impl<'a> GradesCommand<'a> {
+    pub fn new(
+        config: &'a Configs,
+        course: Option<i64>,
+        history: bool,
+        what_if: bool,
+        overview: bool,
+    ) -> Self {
+        Self {
+            config,
+            course,
+            history,
+            what_if,
+            overview,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Command for GradesCommand<'a> {
+    async fn execute(&mut self) -> Result<()> {
+        if self.overview {
+            let overview = load_overview(self.config)?;
+            print_table(OVERVIEW_HEADER, overview_rows(&overview), "No courses");
+            return Ok(());
+        }
+
+        let mut conn = connect_db()?;
+        let courses = self
+            .config
+            .courses
+            .iter()
+            .filter(|course| self.course.map_or(true, |id| id == course.id));
+
+        for course in courses {
+            let name = course.shortname.as_deref().unwrap_or("Unknown");
+            println!("{}", name);
+
+            if self.history {
+                print_history(retrieve_grade_history(&mut conn, course.id)?);
+                continue;
+            }
+
+            let grades = retrieve_course_grades(&mut conn, course.id)?;
+            let rows: Vec<[String; 6]> = grades
+                .iter()
+                .filter_map(|grade| {
+                    Some([
+                        grade.display_name()?.to_string(),
+                        grade.grade(),
+                        grade.range(),
+                        format_percentage(grade.percentage()),
+                        grade.letter().unwrap_or_default().to_string(),
+                        format_date(grade.gradedategraded.unwrap_or_default()),
+                    ])
+                })
+                .collect();
+            print_table(
+                ["Item", "Grade", "Range", "Percent", "Letter", "Graded On"],
+                rows,
+                "No grades",
+            );
+
+            if let Some(standing) = projected_percentage(&grades, &HashMap::new()) {
+                println!("  Standing: {}\n", format_percentage(Some(standing)));
+            }
+            if self.what_if {
+                what_if(&grades)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Asks for hypothetical grades of the items not graded yet and shows the
+// course total they would lead to
+fn what_if(grades: &[GradeItem]) -> Result<()> {
+    let ungraded: Vec<&GradeItem> = grades
+        .iter()
+        .filter(|grade| !grade.is_total() && grade.graderaw.is_none())
+        .collect();
+    if ungraded.is_empty() {
+        println!("  Every item is graded\n");
+        return Ok(());
+    }
+
+    println!("  Enter a grade for each ungraded item, or leave it blank to skip it");
+    let mut hypothetical = HashMap::new();
+    for grade in ungraded {
+        let name = grade.display_name().unwrap_or("Unnamed item");
+        loop {
+            print!("  {} ({}): ", name, grade.range());
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+
+            let input = input.trim();
+            if input.is_empty() {
+                break;
+            }
+            match input.parse::<f64>() {
+                Ok(value) if (grade.grademin..=grade.grademax).contains(&value) => {
+                    hypothetical.insert(grade.id, value);
+                    break;
+                }
+                _ => println!(
+                    "  Enter a number between {} and {}",
+                    format_grade(grade.grademin),
+                    format_grade(grade.grademax)
+                ),
+            }
+        }
+    }
+
+    match projected_percentage(grades, &hypothetical) {
+        Some(projected) => println!("  Projected: {}\n", format_percentage(Some(projected))),
+        None => println!("  Nothing to project from\n"),
+    }
+    Ok(())
+}
+
+// Each item followed by the values it had, one line per observation
+fn print_history(history: Vec<GradeHistory>) {
+    let mut items: Vec<(i64, Vec<GradeHistory>)> = Vec::new();
+    for observed in history {
+        match items.iter_mut().find(|(id, _)| *id == observed.gradeid) {
+            Some((_, values)) => values.push(observed),
+            None => items.push((observed.gradeid, vec![observed])),
+        }
+    }
+    items.retain(|(_, values)| values.iter().any(|value| value.itemname.is_some()));
+
+    if items.is_empty() {
+        println!("  No grades\n");
+        return;
+    }
+
+    for (_, values) in items {
+        let name = values
+            .iter()
+            .rev()
+            .find_map(|value| value.itemname.as_deref())
+            .unwrap_or_default();
+        println!("  {}", name);
+
+        for value in &values {
+            let mut line = format!(
+                "    {:<19}  {:<5}",
+                value.observedat.as_deref().unwrap_or("Unknown"),
+                value
+                    .graderaw
+                    .map_or_else(|| String::from("N/A"), format_grade)
+            );
+            if let Some(graded) = value.gradedategraded {
+                line.push_str(&format!("  graded {}", format_date(graded)));
+            }
+            if let Some(feedback) = value.feedback.as_deref().map(plain_text) {
+                if !feedback.is_empty() {
+                    line.push_str(&format!("  feedback: {}", feedback));
+                }
+            }
+            println!("{}", line.trim_end());
+        }
+    }
+    println!();
+}
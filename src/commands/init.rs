@@ -26,14 +26,16 @@ impl<'a> Command for InitCommand<'a> {
         let conn = connect_db()?;
         create_tables(&conn)?;
 
-        self.config.prompt_config(&self.skin).await?;
+        // A token stored before is used when it isn't entered again
+        self.config.load_token().await?;
+        self.config.prompt_config(self.skin).await?;
 
-        let mut client = ApiClient::from_config(&self.config)?;
+        let mut client = ApiClient::from_config(self.config)?;
         let user_id = get_user_id(&mut client).await?;
         self.config.write_userid(user_id)?;
 
-        let mut client = ApiClient::from_config(&self.config)?;
-        fetch_course_ids_handler(&self.skin, &mut client, &mut self.config).await?;
+        let mut client = ApiClient::from_config(self.config)?;
+        fetch_course_ids_handler(self.skin, &mut client, self.config).await?;
 
         Ok(())
     }
@@ -42,9 +44,9 @@ impl<'a> Command for InitCommand<'a> {
 pub async fn get_user_id(client: &mut ApiClient) -> Result<i64> {
     let response = client.fetch_user_id().await?;
     if let ApiResponse::SiteInfo(info) = response {
-        return Ok(info.userid);
+        Ok(info.userid)
     } else {
-        return Err(eyre::eyre!("Unexpected API response: {:?}", response));
+        Err(eyre::eyre!("Unexpected API response: {:?}", response))
     }
 }
 
@@ -55,7 +57,7 @@ pub async fn fetch_course_ids_handler(
 ) -> Result<()> {
     let response = client.fetch_user_courses().await?;
     if let ApiResponse::Course(course_list) = response {
-        let selected_courses = prompt_courses(&course_list, &skin)?;
+        let selected_courses = prompt_courses(&course_list, skin)?;
         config.write_courses(selected_courses)?;
     } else {
         return Err(eyre::eyre!("Unexpected API response: {:?}", response));
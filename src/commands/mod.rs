@@ -1,5 +1,10 @@
+pub mod changes;
 pub mod command;
 pub mod download;
+pub mod export;
 pub mod fetch;
+pub mod grades;
 pub mod init;
 pub mod parse;
+pub mod todo;
+pub mod watch;
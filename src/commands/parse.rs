@@ -1,11 +1,24 @@
 // commands/parse.rs
 //
 use crate::{
-    commands::command::Command, db::connect_db, models::configs::*,
-    models::course::retrieve_course_structure, models::grades::retrieve_course_grades,
-    parser::parse_course, parser::{save_markdown_to_file, parse_grades}, utils::home_dir,
+    commands::command::Command,
+    db::connect_db,
+    downloader::{expected_local_paths, url_target_path},
+    models::assignments::retrieve_course_assignments,
+    models::changes::retrieve_course_changes,
+    models::completion::retrieve_activity_completion,
+    models::configs::*,
+    models::course::retrieve_course_structure,
+    models::embedded::retrieve_course_embedded_files,
+    models::grades::retrieve_course_grades,
+    models::urls::retrieve_course_urls,
+    parser::org,
+    parser::template::{course_context, render_course},
+    parser::vault::{parse_vault, save_notes, VaultCourse},
+    parser::{parse_changes, parse_links, resolve_local_links, save_markdown_to_file},
+    utils::{create_dir, strip_emojis},
 };
-use {async_trait::async_trait, eyre::Result};
+use {async_trait::async_trait, eyre::Result, std::collections::HashMap};
 
 pub struct ParseCommand<'a> {
     config: &'a Configs,
@@ -31,18 +44,110 @@ pub fn parse_command_handler(config: &Configs) -> Result<()> {
         let structure = retrieve_course_structure(&mut conn, course.id)?;
         let grades = retrieve_course_grades(&mut conn, course.id)?;
 
-        let mut course_md = parse_course(structure);
-        let grades_md = parse_grades(grades);
-        course_md.push_str(&grades_md);
+        let embedded = retrieve_course_embedded_files(&mut conn, course.id)?;
 
-        let mut file_path = home_dir();
-        if let Some(course_path) = config.get_course_path(course.id) {
-            file_path = file_path.join(course_path);
+        // The course markdown sits next to the course directory
+        let course_dir = config.get_course_dir(course.id);
+        let document_dir = course_dir.parent().unwrap_or(&course_dir);
+        let paths = expected_local_paths(config, course.id, &structure, &embedded);
+        let local_paths = resolve_local_links(&paths, document_dir);
+
+        create_dir(course_dir.to_str().unwrap())?;
+
+        if config.output.formats.contains(&OutputFormat::Org) {
+            let assignments = retrieve_course_assignments(&mut conn, course.id)?;
+            let title = config
+                .get_course_name(course.id)
+                .cloned()
+                .unwrap_or_else(|| course.id.to_string());
+
+            let mut course_org =
+                org::parse_course(&title, structure.clone(), &assignments, &local_paths);
+            course_org.push_str(&org::parse_grades(&grades));
+            if config.output.strip_emojis {
+                course_org = strip_emojis(&course_org);
+            }
+            org::save_org_to_file(course_org, course_dir.to_str().unwrap())?;
+        }
+
+        if config.output.formats.contains(&OutputFormat::Vault) {
+            let assignments = retrieve_course_assignments(&mut conn, course.id)?;
+            let name = config
+                .get_course_name(course.id)
+                .cloned()
+                .unwrap_or_else(|| course.id.to_string());
+            let vault_course = VaultCourse {
+                id: course.id,
+                name: &name,
+                base_url: &config.api.base_url,
+            };
+
+            let notes_dir = course_dir.join("notes");
+            let note_paths = resolve_local_links(&paths, &notes_dir);
+            let mut notes = parse_vault(
+                &vault_course,
+                structure.clone(),
+                &assignments,
+                &grades,
+                &note_paths,
+            );
+            if config.output.strip_emojis {
+                notes
+                    .iter_mut()
+                    .for_each(|note| note.content = strip_emojis(&note.content));
+            }
+            save_notes(notes, &notes_dir)?;
         }
-        if let Some(name) = config.get_course_name(course.id) {
-            file_path = file_path.join(name);
+
+        if config.output.formats.contains(&OutputFormat::Markdown) {
+            let assignments = retrieve_course_assignments(&mut conn, course.id)?;
+            let name = config
+                .get_course_name(course.id)
+                .cloned()
+                .unwrap_or_else(|| course.id.to_string());
+            let context = course_context(
+                course.id,
+                &name,
+                structure,
+                &assignments,
+                grades,
+                &retrieve_activity_completion(&mut conn, course.id)?,
+                &local_paths,
+            );
+            let mut course_md =
+                render_course(config.get_course_template(course.id).as_deref(), &context)?;
+            if config.output.strip_emojis {
+                course_md = strip_emojis(&course_md);
+            }
+            save_markdown_to_file(course_md, course_dir.to_str().unwrap())?;
+        }
+
+        if config.output.changelog {
+            let changes = retrieve_course_changes(&mut conn, course.id)?;
+            let mut changes_md = parse_changes(changes);
+            if config.output.strip_emojis {
+                changes_md = strip_emojis(&changes_md);
+            }
+            let changes_path = course_dir.join("changes");
+            save_markdown_to_file(changes_md, changes_path.to_str().unwrap())?;
+        }
+
+        let urls = retrieve_course_urls(&mut conn, course.id)?;
+        if !urls.is_empty() {
+            let targets: HashMap<_, _> = urls
+                .iter()
+                .map(|url| (url.externalurl.clone(), url_target_path(config, url)))
+                .collect();
+            let link_paths = resolve_local_links(&targets, &course_dir);
+
+            let links_path = course_dir.join("links");
+            create_dir(links_path.to_str().unwrap())?;
+            let mut links_md = parse_links(urls, &link_paths);
+            if config.output.strip_emojis {
+                links_md = strip_emojis(&links_md);
+            }
+            save_markdown_to_file(links_md, links_path.to_str().unwrap())?;
         }
-        save_markdown_to_file(course_md, file_path.to_str().unwrap())?;
     }
     Ok(())
 }
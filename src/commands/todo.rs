@@ -0,0 +1,110 @@
+// commands/todo.rs
+//
+use crate::{
+    commands::command::Command,
+    db::connect_db,
+    models::{
+        assignments::retrieve_course_assignments,
+        completion::{
+            retrieve_activity_completion, retrieve_course_completion, retrieve_required_activities,
+        },
+        configs::*,
+        course::retrieve_course_structure,
+    },
+    ui::table::print_table,
+    utils::format_date,
+};
+use {async_trait::async_trait, eyre::Result};
+
+pub struct TodoCommand<'a> {
+    config: &'a Configs,
+    course: Option<i64>,
+}
+
+impl<'a> TodoCommand<'a> {
+    pub fn new(config: &'a Configs, course: Option<i64>) -> Self {
+        Self { config, course }
+    }
+}
+
+#[async_trait]
+impl<'a> Command for TodoCommand<'a> {
+    async fn execute(&mut self) -> Result<()> {
+        let mut conn = connect_db()?;
+        let courses = self
+            .config
+            .courses
+            .iter()
+            .filter(|course| self.course.map_or(true, |id| id == course.id));
+
+        for course in courses {
+            let name = course.shortname.as_deref().unwrap_or("Unknown");
+            let completed = retrieve_course_completion(&mut conn, course.id)?
+                .is_some_and(|completion| completion.completed);
+            if completed {
+                println!("{} (course completed)", name);
+            } else {
+                println!("{}", name);
+            }
+
+            let completion = retrieve_activity_completion(&mut conn, course.id)?;
+            if !completion.iter().any(|status| status.is_tracked()) {
+                println!("  Completion isn't tracked\n");
+                continue;
+            }
+
+            // Courses that complete without activities have nothing required,
+            // every tracked activity is listed for those
+            let required = retrieve_required_activities(&mut conn, course.id)?;
+            if required.is_empty() {
+                println!(
+                    "  No activities are required to complete the course, listing all tracked ones"
+                );
+            }
+
+            let structure = retrieve_course_structure(&mut conn, course.id)?;
+            let assignments = retrieve_course_assignments(&mut conn, course.id)?;
+            let modules = structure.iter().flat_map(|section| {
+                section
+                    .modules
+                    .iter()
+                    .map(move |module| (section.name.as_str(), module))
+            });
+
+            // In course order, activities the course no longer shows are left out
+            let rows: Vec<[String; 5]> = modules
+                .filter_map(|(section, module)| {
+                    if !required.is_empty() && !required.contains(&module.id) {
+                        return None;
+                    }
+                    let status = completion
+                        .iter()
+                        .find(|status| status.cmid == module.id && status.is_open())?;
+                    let due = assignments
+                        .iter()
+                        .find(|assignment| assignment.cmid == module.id)
+                        .filter(|assignment| assignment.duedate > 0)
+                        .map_or_else(String::new, |assignment| format_date(assignment.duedate));
+                    let state = match (status.state, status.tracking) {
+                        (3, _) => "Failed",
+                        (_, 1) => "Mark as done",
+                        _ => "Not done",
+                    };
+                    Some([
+                        section.to_string(),
+                        module.name.clone(),
+                        module.modname.clone().unwrap_or_default(),
+                        due,
+                        state.to_string(),
+                    ])
+                })
+                .collect();
+            print_table(
+                ["Section", "Activity", "Type", "Due", "State"],
+                rows,
+                "Nothing left to do",
+            );
+        }
+        Ok(())
+    }
+}
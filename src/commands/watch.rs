@@ -0,0 +1,173 @@
+// commands/watch.rs
+//
+// Runs the fetch, download and parse steps of `DefaultCommand` on a schedule
+// until interrupted.
+use crate::{
+    commands::command::{Command, DefaultCommand},
+    db::connect_db,
+    models::{
+        changes::{retrieve_changes, ChangeKind},
+        configs::*,
+    },
+    utils::SyncLock,
+    ws::ApiClient,
+};
+use chrono::Local;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use {async_trait::async_trait, eyre::Result};
+
+// Incremental fetches can miss what Moodle doesn't report as updated, such
+// as files replaced without touching their module
+const FULL_FETCH_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct WatchCommand<'a> {
+    config: &'a Configs,
+    client: Option<ApiClient>, // owned ApiClient instance, none when offline
+    interval: Duration,
+}
+
+impl<'a> WatchCommand<'a> {
+    pub fn new(config: &'a Configs, client: Option<ApiClient>, interval: Duration) -> Self {
+        Self {
+            config,
+            client,
+            interval,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Command for WatchCommand<'a> {
+    async fn execute(&mut self) -> Result<()> {
+        if self.client.is_none() {
+            return Err(eyre::eyre!(
+                "watch needs the network, run it without --offline"
+            ));
+        }
+        if self.interval < Duration::from_secs(60) {
+            return Err(eyre::eyre!("The interval has to be at least a minute"));
+        }
+
+        // A signal lets the running step finish, or ends the wait for the next
+        // sync. A second one exits right away, say when a fetch hangs.
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(Notify::new());
+        {
+            let stop = stop.clone();
+            let stopped = stopped.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = shutdown_signal().await {
+                        log::error!("Failed to listen for signals: {:?}", e);
+                        return;
+                    }
+                    if stop.swap(true, Ordering::SeqCst) {
+                        log::warn!("Stopping without waiting for the running step");
+                        // Exiting skips dropping the running step's lock
+                        let _ = std::fs::remove_file(SyncLock::path());
+                        std::process::exit(130);
+                    }
+                    log::info!(
+                        "Stopping, waiting for the running step to finish, press Ctrl-C again to quit now"
+                    );
+                    stopped.notify_one();
+                }
+            });
+        }
+
+        log::info!("Syncing every {}", format_interval(self.interval));
+        let mut last_full: Option<Instant> = None;
+        loop {
+            let started = Instant::now();
+            // The first sync is a full one, then one a day
+            let full = last_full.is_none_or(|last| last.elapsed() >= FULL_FETCH_EVERY);
+            if full {
+                log::info!("Fetching every course in full");
+            }
+            let mut command =
+                DefaultCommand::new(self.config, self.client.clone(), full).stop_on(stop.clone());
+            match command.execute().await.and_then(|_| changes_summary()) {
+                Ok(summary) => {
+                    if full {
+                        last_full = Some(started);
+                    }
+                    log::info!(
+                        "Sync finished in {}s, {}",
+                        started.elapsed().as_secs(),
+                        summary
+                    )
+                }
+                Err(e) => log::error!("Sync failed: {:?}", e),
+            }
+
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            // Syncs that run longer than the interval are followed right away
+            let wait = self.interval.saturating_sub(started.elapsed());
+            let next = chrono::Duration::from_std(wait)
+                .ok()
+                .and_then(|wait| Local::now().checked_add_signed(wait));
+            match next {
+                Some(next) => log::info!("Next sync at {}", next.format("%Y-%m-%d %H:%M")),
+                None => log::info!("Next sync in {}", format_interval(wait)),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = stopped.notified() => break,
+            }
+        }
+
+        log::info!("Stopped watching");
+        Ok(())
+    }
+}
+
+// What the last fetch recorded, see `ChangesCommand`
+fn changes_summary() -> Result<String> {
+    let changes = retrieve_changes(&mut connect_db()?, 1, None)?;
+    if changes.is_empty() {
+        return Ok(String::from("nothing changed"));
+    }
+    let count = |kind: ChangeKind| changes.iter().filter(|change| change.kind == kind).count();
+    Ok(format!(
+        "{} added, {} removed, {} modified",
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Modified)
+    ))
+}
+
+fn format_interval(interval: Duration) -> String {
+    let seconds = interval.as_secs();
+    match seconds {
+        s if s % 86400 == 0 => format!("{}d", s / 86400),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> Result<()> {
+    Ok(tokio::signal::ctrl_c().await?)
+}
@@ -0,0 +1,337 @@
+// credentials.rs
+//
+// Keeps API tokens out of `config.toml`. A token is read from the first of
+// the environment, the Secret Service keyring and `credentials.toml` that
+// has one, falling back to a plaintext `token` left in `config.toml`.
+use crate::utils::{add_secret, config_dir};
+use eyre::{Result, WrapErr};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const ENV_VAR: &str = "MOODL_RS_TOKEN";
+// Key of the default site and account in `credentials.toml`
+const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenSource {
+    Environment,
+    Keyring,
+    CredentialsFile,
+    ConfigFile,
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TokenSource::Environment => "the environment",
+            TokenSource::Keyring => "the keyring",
+            TokenSource::CredentialsFile => "'credentials.toml'",
+            TokenSource::ConfigFile => "'config.toml'",
+        })
+    }
+}
+
+/// `MOODL_RS_TOKEN` for the default profile, `MOODL_RS_TOKEN_<NAME>` for others
+pub fn env_var(profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("{}_{}", ENV_VAR, name.to_uppercase().replace('-', "_")),
+        None => ENV_VAR.to_string(),
+    }
+}
+
+pub fn credentials_path() -> PathBuf {
+    config_dir().join("credentials.toml")
+}
+
+/// The profile's token and where it was found, `config_token` being the
+/// plaintext one from `config.toml`. Found tokens are redacted from the log.
+pub async fn load_token(
+    profile: Option<&str>,
+    config_token: &str,
+) -> Result<Option<(String, TokenSource)>> {
+    load_token_from(profile, config_token, &credentials_path()).await
+}
+
+async fn load_token_from(
+    profile: Option<&str>,
+    config_token: &str,
+    credentials: &Path,
+) -> Result<Option<(String, TokenSource)>> {
+    let found = match find_token(profile, credentials).await? {
+        Some(found) => Some(found),
+        None if !config_token.is_empty() => {
+            log::warn!(
+                "The API token is stored in plaintext in 'config.toml', run init again to move it"
+            );
+            Some((config_token.to_string(), TokenSource::ConfigFile))
+        }
+        None => None,
+    };
+    if let Some((token, source)) = &found {
+        add_secret(token);
+        log::debug!("Using the API token from {}", source);
+    }
+    Ok(found)
+}
+
+async fn find_token(
+    profile: Option<&str>,
+    credentials: &Path,
+) -> Result<Option<(String, TokenSource)>> {
+    if let Some(token) = std::env::var(env_var(profile))
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        return Ok(Some((token, TokenSource::Environment)));
+    }
+    match keyring::read(profile).await {
+        Ok(Some(token)) => return Ok(Some((token, TokenSource::Keyring))),
+        Ok(None) => {}
+        Err(e) => log::debug!("Keyring unavailable: {}", e),
+    }
+    Ok(read_credentials(credentials)?
+        .remove(profile.unwrap_or(DEFAULT_PROFILE))
+        .map(|token| (token, TokenSource::CredentialsFile)))
+}
+
+/// Stores the profile's token in the keyring, or `credentials.toml` when
+/// there is none, and returns where it went
+pub async fn store_token(profile: Option<&str>, token: &str) -> Result<TokenSource> {
+    add_secret(token);
+    let path = credentials_path();
+    match keyring::write(profile, token).await {
+        Ok(()) => {
+            // A copy left in the file would outlive the keyring entry
+            let mut credentials = read_credentials(&path)?;
+            if credentials
+                .remove(profile.unwrap_or(DEFAULT_PROFILE))
+                .is_some()
+            {
+                write_credentials(&path, &credentials)?;
+            }
+            Ok(TokenSource::Keyring)
+        }
+        Err(e) => {
+            log::debug!("Keyring unavailable: {}", e);
+            let mut credentials = read_credentials(&path)?;
+            credentials.insert(
+                profile.unwrap_or(DEFAULT_PROFILE).to_string(),
+                token.to_string(),
+            );
+            write_credentials(&path, &credentials)?;
+            Ok(TokenSource::CredentialsFile)
+        }
+    }
+}
+
+fn read_credentials(path: &Path) -> Result<BTreeMap<String, String>> {
+    let Ok(data) = fs::read_to_string(path) else {
+        return Ok(BTreeMap::new());
+    };
+    warn_if_readable_by_others(path);
+    toml::from_str(&data).wrap_err("Failed to parse 'credentials.toml'")
+}
+
+fn write_credentials(path: &Path, credentials: &BTreeMap<String, String>) -> Result<()> {
+    let data = toml::to_string(credentials).wrap_err("Failed to serialize credentials")?;
+    let mut file =
+        owner_only_file(path).wrap_err("Failed to open 'credentials.toml' for writing")?;
+    file.write_all(data.as_bytes())
+        .wrap_err("Failed to write 'credentials.toml'")
+}
+
+// Created with mode 0600, an existing file is narrowed to it before writing
+#[cfg(unix)]
+fn owner_only_file(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn owner_only_file(path: &Path) -> std::io::Result<fs::File> {
+    fs::File::create(path)
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            log::warn!(
+                "'{}' can be read by other users, run chmod 600 on it",
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &Path) {}
+
+#[cfg(target_os = "linux")]
+mod keyring {
+    use eyre::{eyre, Result};
+    use secret_service::{EncryptionType, SecretService};
+    use std::{collections::HashMap, time::Duration};
+
+    // A session bus without a Secret Service shouldn't hold up a sync
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn attributes(profile: Option<&str>) -> HashMap<&str, &str> {
+        HashMap::from([
+            ("application", "moodl-rs"),
+            ("profile", profile.unwrap_or(super::DEFAULT_PROFILE)),
+        ])
+    }
+
+    async fn connect() -> Result<SecretService<'static>> {
+        if std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() {
+            return Err(eyre!("no D-Bus session bus"));
+        }
+        Ok(
+            tokio::time::timeout(CONNECT_TIMEOUT, SecretService::connect(EncryptionType::Dh))
+                .await
+                .map_err(|_| eyre!("timed out connecting to the Secret Service"))??,
+        )
+    }
+
+    pub async fn read(profile: Option<&str>) -> Result<Option<String>> {
+        let service = connect().await?;
+        let items = service.search_items(attributes(profile)).await?;
+        let item = match (items.unlocked.first(), items.locked.first()) {
+            (Some(item), _) => item,
+            // Asks the user to unlock the keyring
+            (None, Some(item)) => {
+                item.unlock().await?;
+                item
+            }
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(String::from_utf8(item.get_secret().await?)?))
+    }
+
+    pub async fn write(profile: Option<&str>, token: &str) -> Result<()> {
+        let service = connect().await?;
+        let collection = service.get_default_collection().await?;
+        collection.ensure_unlocked().await?;
+        collection
+            .create_item(
+                &format!(
+                    "moodl-rs API token ({})",
+                    profile.unwrap_or(super::DEFAULT_PROFILE)
+                ),
+                attributes(profile),
+                token.as_bytes(),
+                true,
+                "text/plain",
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod keyring {
+    use eyre::{eyre, Result};
+
+    pub async fn read(_profile: Option<&str>) -> Result<Option<String>> {
+        Err(eyre!("no Secret Service on this platform"))
+    }
+
+    pub async fn write(_profile: Option<&str>, _token: &str) -> Result<()> {
+        Err(eyre!("no Secret Service on this platform"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_an_environment_variable_per_profile() {
+        assert_eq!(env_var(None), "MOODL_RS_TOKEN");
+        assert_eq!(env_var(Some("college")), "MOODL_RS_TOKEN_COLLEGE");
+        assert_eq!(env_var(Some("night-school")), "MOODL_RS_TOKEN_NIGHT_SCHOOL");
+    }
+
+    // No keyring is running where the tests are, and none would hold a
+    // token for this profile
+    #[tokio::test]
+    async fn looks_up_tokens_in_order() {
+        let dir = std::env::temp_dir().join(format!("moodl-rs-credentials-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.toml");
+        let profile = Some("lookup-order");
+        let found = |token: &str, source| Some((token.to_string(), source));
+
+        assert_eq!(load_token_from(profile, "", &path).await.unwrap(), None);
+        assert_eq!(
+            load_token_from(profile, "from-config", &path)
+                .await
+                .unwrap(),
+            found("from-config", TokenSource::ConfigFile)
+        );
+
+        let credentials =
+            BTreeMap::from([(String::from("lookup-order"), String::from("from-file"))]);
+        write_credentials(&path, &credentials).unwrap();
+        assert_eq!(
+            load_token_from(profile, "from-config", &path)
+                .await
+                .unwrap(),
+            found("from-file", TokenSource::CredentialsFile)
+        );
+        // Other profiles' tokens are not used
+        assert_eq!(
+            load_token_from(Some("lookup-other"), "", &path)
+                .await
+                .unwrap(),
+            None
+        );
+
+        std::env::set_var(env_var(profile), "from-env");
+        let from_env = load_token_from(profile, "from-config", &path)
+            .await
+            .unwrap();
+        std::env::remove_var(env_var(profile));
+        assert_eq!(from_env, found("from-env", TokenSource::Environment));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_credentials_readable_only_by_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("moodl-rs-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.toml");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let credentials = BTreeMap::from([(String::from("default"), String::from("token"))]);
+        write_credentials(&path, &credentials).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // A file made readable by others is narrowed again when rewritten
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_credentials(&path, &credentials).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(read_credentials(&path).unwrap(), credentials);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
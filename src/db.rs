@@ -3,29 +3,49 @@
 // where to store your database, default is your system data directory
 // linux/mac: ~/.local/share/moodl-rs/moodl-rs.db
 // windows: %USERPROFILE%/.local/share/moodl-rs/moodl-rs.db
+// profiles other than the default one each have their own, see `use_database`
 use crate::utils::*;
 use eyre::{Result, WrapErr};
 use rusqlite::types::ToSql;
 use rusqlite::{params, Connection, Transaction};
-use std::fs;
+use std::{fs, path::PathBuf, sync::RwLock};
+
+static DATABASE: RwLock<Option<PathBuf>> = RwLock::new(None);
 
 pub fn initialize_db() -> Result<()> {
-    let data_directory = data_dir();
+    let db_path = db_path();
 
-    if !data_directory.exists() {
-        fs::create_dir_all(&data_directory).wrap_err("Failed to create data directory")?;
+    if let Some(data_directory) = db_path.parent() {
+        if !data_directory.exists() {
+            fs::create_dir_all(data_directory).wrap_err("Failed to create data directory")?;
+        }
     }
 
-    let db_path = data_directory.join("moodl-rs.db");
-
     let conn = Connection::open(db_path).wrap_err("Failed to open connection to the database")?;
     create_tables(&conn).wrap_err("Failed to create tables in the database")?;
 
     Ok(())
 }
 
+/// The database every connection opens, `moodl-rs.db` unless a profile
+/// picked another
+pub fn db_path() -> PathBuf {
+    DATABASE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| data_dir().join("moodl-rs.db"))
+}
+
+/// Opens `path` for every following connection, creating its tables
+pub fn use_database(path: PathBuf) -> Result<()> {
+    log::debug!("Using the database at {}", path.display());
+    *DATABASE.write().unwrap() = Some(path);
+    initialize_db()
+}
+
 pub fn connect_db() -> Result<Connection> {
-    let db_path = data_dir().join("moodl-rs.db");
+    let db_path = db_path();
     let conn = Connection::open(db_path).wrap_err("Failed to connect to the database")?;
     Ok(conn)
 }
@@ -76,7 +96,7 @@ pub fn generic_retrieve<T: Retrievable>(tx: &Transaction) -> Result<Vec<T>> {
     Ok(results)
 }
 
-pub fn retrieve_param<T: Retrievable>(tx: &Transaction, params: &[&(dyn ToSql)]) -> Result<Vec<T>> {
+pub fn retrieve_param<T: Retrievable>(tx: &Transaction, params: &[&dyn ToSql]) -> Result<Vec<T>> {
     let mut stmt = tx
         .prepare(T::select_query())
         .wrap_err_with(|| format!("Failed to prepare query: {}", T::select_query()))?;
@@ -92,7 +112,7 @@ pub fn retrieve_param<T: Retrievable>(tx: &Transaction, params: &[&(dyn ToSql)])
 }
 
 pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
-        conn.execute(
+    conn.execute(
         "CREATE TABLE IF NOT EXISTS Assignments (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             assignid INTEGER,
@@ -117,18 +137,23 @@ pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             gradeid INTEGER,
             itemname TEXT,
+            itemtype TEXT,
             itemmodule TEXT,
             iteminstance INTEGER,
             itemnumber INTEGER,
             idnumber TEXT,
             categoryid INTEGER,
             cmid INTEGER,
-            graderaw INTEGER,
+            graderaw REAL,
             gradedatesubmitted DATETIME,
             gradedategraded DATETIME,
-            grademin INTEGER,
-            grademax INTEGER,
+            grademin REAL,
+            grademax REAL,
             feedback TEXT,
+            weightraw REAL,
+            gradeformatted TEXT,
+            percentageformatted TEXT,
+            lettergradeformatted TEXT,
             lastfetched DATETIME,
             courseid INTEGER,
             UNIQUE(gradeid)
@@ -136,6 +161,118 @@ pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
         (),
     )
     .wrap_err("Failed to create Grades table")?;
+    add_missing_column(conn, "Grades", "itemtype", "TEXT")?;
+    add_missing_column(conn, "Grades", "weightraw", "REAL")?;
+    add_missing_column(conn, "Grades", "gradeformatted", "TEXT")?;
+    add_missing_column(conn, "Grades", "percentageformatted", "TEXT")?;
+    add_missing_column(conn, "Grades", "lettergradeformatted", "TEXT")?;
+    // Older databases declare the grade columns INTEGER. SQLite keeps a value
+    // that isn't a whole number as REAL in those, so decimals survive as is.
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS CourseSync (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            courseid INTEGER,
+            lastsync INTEGER,
+            UNIQUE(courseid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create CourseSync table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Reminders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cmid INTEGER,
+            duedate INTEGER,
+            lead INTEGER,
+            sentat DATETIME,
+            UNIQUE(cmid, duedate, lead)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create Reminders table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ActivityCompletion (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cmid INTEGER,
+            courseid INTEGER,
+            modname TEXT,
+            instance INTEGER,
+            state INTEGER,
+            timecompleted DATETIME,
+            tracking INTEGER,
+            lastfetched DATETIME,
+            UNIQUE(cmid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create ActivityCompletion table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS CourseCompletion (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            courseid INTEGER,
+            completed BOOLEAN,
+            aggregation INTEGER,
+            lastfetched DATETIME,
+            UNIQUE(courseid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create CourseCompletion table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS RequiredActivities (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            courseid INTEGER,
+            cmid INTEGER,
+            UNIQUE(courseid, cmid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create RequiredActivities table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS CourseTotals (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            courseid INTEGER,
+            grade TEXT,
+            rawgrade REAL,
+            rank INTEGER,
+            lastfetched DATETIME,
+            UNIQUE(courseid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create CourseTotals table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS GradeHistory (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            gradeid INTEGER,
+            courseid INTEGER,
+            itemname TEXT,
+            graderaw REAL,
+            feedback TEXT,
+            gradedategraded DATETIME,
+            observedat DATETIME,
+            FOREIGN KEY (gradeid) REFERENCES Grades(gradeid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create GradeHistory table")?;
+    // Grades stored before the history was kept start it with their current values
+    conn.execute(
+        "INSERT INTO GradeHistory (
+            gradeid, courseid, itemname, graderaw, feedback, gradedategraded, observedat)
+            SELECT gradeid, courseid, itemname, graderaw, feedback, gradedategraded,
+                COALESCE(lastfetched, CURRENT_TIMESTAMP)
+            FROM Grades WHERE gradeid NOT IN (SELECT gradeid FROM GradeHistory)",
+        (),
+    )
+    .wrap_err("Failed to fill in the GradeHistory table")?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS Sections (
@@ -145,11 +282,13 @@ pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
             summary TEXT,
             lastfetched DATETIME,
             courseid INTEGER,
+            removedat DATETIME,
             UNIQUE(sectionid)
         );",
         (),
     )
     .wrap_err("Failed to create Sections table")?;
+    add_missing_column(conn, "Sections", "removedat", "DATETIME")?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS Scorms (
@@ -181,15 +320,19 @@ pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
             name TEXT,
             instance INTEGER,
             contextid INTEGER,
+            modname TEXT,
             description TEXT,
             lastfetched DATETIME,
             section_id INTEGER,
+            removedat DATETIME,
             UNIQUE(moduleid),
             FOREIGN KEY (section_id) REFERENCES Sections(sectionid)
         );",
         (),
     )
     .wrap_err("Failed to create Modules table")?;
+    add_missing_column(conn, "Modules", "modname", "TEXT")?;
+    add_missing_column(conn, "Modules", "removedat", "DATETIME")?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS Files (
@@ -200,12 +343,98 @@ pub fn create_tables(conn: &rusqlite::Connection) -> Result<()> {
             timemodified DATETIME,
             lastfetched DATETIME,
             module_id INTEGER,
+            removedat DATETIME,
             UNIQUE(filename),
             FOREIGN KEY (module_id) REFERENCES Modules(moduleid)
         );",
         (),
     )
     .wrap_err("Failed to create Files table")?;
+    add_missing_column(conn, "Files", "removedat", "DATETIME")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Urls (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            urlid INTEGER,
+            coursemodule INTEGER,
+            name TEXT,
+            intro TEXT,
+            externalurl TEXT,
+            localpath TEXT,
+            timemodified DATETIME,
+            lastfetched DATETIME,
+            courseid INTEGER,
+            UNIQUE(urlid),
+            FOREIGN KEY (coursemodule) REFERENCES Modules(moduleid)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create Urls table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS EmbeddedFiles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT,
+            localpath TEXT,
+            lastfetched DATETIME,
+            courseid INTEGER,
+            UNIQUE(url)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create EmbeddedFiles table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ChangeSets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            createdat DATETIME
+        );",
+        (),
+    )
+    .wrap_err("Failed to create ChangeSets table")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS Changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            changeset INTEGER,
+            courseid INTEGER,
+            entity TEXT,
+            key TEXT,
+            kind TEXT,
+            name TEXT,
+            detail TEXT,
+            detectedat DATETIME,
+            FOREIGN KEY (changeset) REFERENCES ChangeSets(id)
+        );",
+        (),
+    )
+    .wrap_err("Failed to create Changes table")?;
+
+    Ok(())
+}
+
+// Databases created by older versions lack columns added since,
+// `CREATE TABLE IF NOT EXISTS` leaves those tables untouched
+fn add_missing_column(
+    conn: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let exists = conn
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ))?
+        .exists([column])?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            (),
+        )
+        .wrap_err_with(|| format!("Failed to add column {} to {} table", column, table))?;
+    }
 
     Ok(())
 }
@@ -2,16 +2,23 @@
 //
 use crate::{
     db::connect_db,
-    models::course::CourseFile,
     models::configs::Configs,
+    models::course::{CourseFile, CourseSection},
+    models::embedded::{update_embedded_path_in_db, EmbeddedFile},
+    models::urls::{update_url_path_in_db, UrlModule},
     utils::create_dir,
-    utils::home_dir,
     ws::ApiClient,
 };
+use deunicode::deunicode;
 use eyre::Result;
-use log;
+use percent_encoding::percent_decode_str;
 use regex::Regex;
 use rusqlite::params;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub async fn save_files(
     api_client: &ApiClient,
@@ -21,27 +28,13 @@ pub async fn save_files(
     for file in files {
         let filename = file.filename.unwrap();
         let fileurl = file.fileurl.unwrap();
-        let mut file_path = home_dir();
 
         let course_id = get_course_id(&filename)
             .unwrap()
             .expect("No course id associated to that file");
+        let file_path = course_file_path(config, course_id, &filename);
 
-        if let Some(path) = config.get_course_path(course_id) {
-            file_path = file_path.join(path);
-        }
-        if let Some(name) = config.get_course_name(course_id) {
-            file_path = file_path.join(name);
-        }
-
-        if let Err(e) = handle_file_operations(
-            &api_client,
-            &file_path.to_str().unwrap(),
-            &filename,
-            &fileurl,
-        )
-        .await
-        {
+        if let Err(e) = handle_file_operations(api_client, &file_path, &filename, &fileurl).await {
             log::error!("Error handling file operations: {:?}", e);
         }
     }
@@ -51,16 +44,15 @@ pub async fn save_files(
 
 async fn handle_file_operations(
     api_client: &ApiClient,
-    file_path: &str,
+    file_path: &Path,
     filename: &str,
     fileurl: &str,
 ) -> Result<()> {
-    let sanitized_file_name = sanitize_filename(&filename);
-    let clean_file_path = format!("{}/{}", file_path, sanitized_file_name);
+    let clean_file_path = file_path.to_str().unwrap();
 
-    match create_dir(&clean_file_path) {
-        Ok(_) => match api_client.download_file(&fileurl, &clean_file_path).await {
-            Ok(_) => match update_file_paths_in_db(filename, &clean_file_path) {
+    match create_dir(clean_file_path) {
+        Ok(_) => match api_client.download_file(fileurl, clean_file_path).await {
+            Ok(_) => match update_file_paths_in_db(filename, clean_file_path) {
                 Ok(_) => {}
                 Err(e) => log::error!("Failed to update DB for Files: {:?}", e),
             },
@@ -72,19 +64,323 @@ async fn handle_file_operations(
     Ok(())
 }
 
-fn sanitize_filename(filename: &str) -> String {
+pub async fn save_urls(
+    api_client: &ApiClient,
+    urls: Vec<UrlModule>,
+    config: &Configs,
+) -> Result<()> {
+    let conn = connect_db()?;
+
+    for url in urls {
+        let shortcut_path = shortcut_path(config, &url);
+
+        if let Err(e) = create_dir(shortcut_path.to_str().unwrap())
+            .and_then(|_| write_shortcut(&shortcut_path, &url.name, &url.externalurl))
+        {
+            log::error!("Failed to write shortcut for '{}': {:?}", url.name, e);
+            continue;
+        }
+
+        if !api_client.is_allowed_url(&url.externalurl) {
+            log::debug!("Not downloading '{}', host is not allowed", url.externalurl);
+            continue;
+        }
+
+        let target_path = url_target_path(config, &url);
+        let target_path = target_path.to_str().unwrap();
+        match api_client
+            .download_file(&url.externalurl, target_path)
+            .await
+        {
+            Ok(_) => {
+                if let Err(e) = update_url_path_in_db(&conn, url.id, target_path) {
+                    log::error!("Failed to update DB for Urls: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to download linked file: {:?}", e),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn save_embedded_files(
+    api_client: &ApiClient,
+    files: Vec<EmbeddedFile>,
+    config: &Configs,
+) -> Result<()> {
+    let conn = connect_db()?;
+
+    for file in files {
+        let file_path = embedded_file_path(config, file.courseid, &file.url);
+        let file_path = file_path.to_str().unwrap();
+
+        match create_dir(file_path) {
+            Ok(_) => match api_client.download_file(&file.url, file_path).await {
+                Ok(_) => {
+                    if let Err(e) = update_embedded_path_in_db(&conn, &file.url, file_path) {
+                        log::error!("Failed to update DB for EmbeddedFiles: {:?}", e);
+                    }
+                }
+                Err(e) => log::error!("Failed to download embedded file: {:?}", e),
+            },
+            Err(e) => log::error!("Failed to create directory: {:?}", e),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn course_file_path(config: &Configs, course_id: i64, filename: &str) -> PathBuf {
+    config
+        .get_course_dir(course_id)
+        .join(sanitize_filename(config, filename))
+}
+
+pub fn embedded_file_path(config: &Configs, course_id: i64, url: &str) -> PathBuf {
+    config
+        .get_course_dir(course_id)
+        .join("embedded")
+        .join(embedded_filename(config, url))
+}
+
+pub fn shortcut_path(config: &Configs, url: &UrlModule) -> PathBuf {
+    config
+        .get_course_dir(url.course)
+        .join(shortcut_filename(config, &url.name))
+}
+
+pub fn url_target_path(config: &Configs, url: &UrlModule) -> PathBuf {
+    config
+        .get_course_dir(url.course)
+        .join(url_target_filename(config, url))
+}
+
+/// Where the download step saves every file of a course, keyed by remote url.
+/// Paths are predicted rather than read from the database so that parsing
+/// before a download yields the same links once the files arrive.
+pub fn expected_local_paths(
+    config: &Configs,
+    course_id: i64,
+    sections: &[CourseSection],
+    embedded: &[EmbeddedFile],
+) -> HashMap<String, PathBuf> {
+    let mut paths = HashMap::new();
+
+    for module in sections.iter().flat_map(|section| &section.modules) {
+        if module.modname.as_deref() == Some("url") {
+            continue;
+        }
+        for file in module.contents.iter().flatten() {
+            if let (Some(name), Some(url)) = (&file.filename, &file.fileurl) {
+                paths.insert(url.clone(), course_file_path(config, course_id, name));
+            }
+        }
+    }
+
+    for file in embedded {
+        paths.insert(
+            file.url.clone(),
+            embedded_file_path(config, course_id, &file.url),
+        );
+    }
+
+    paths
+}
+
+// Embedded files commonly share names like `image.png`, so keep the
+// context, component and item segments following `pluginfile.php`
+fn embedded_filename(config: &Configs, url: &str) -> String {
+    let path = reqwest::Url::parse(url)
+        .map(|parsed| parsed.path().to_string())
+        .unwrap_or_else(|_| url.to_string());
+    let relative = path
+        .split_once("/pluginfile.php/")
+        .map_or(path.as_str(), |(_, rest)| rest);
+    let name: Vec<String> = relative.split('/').map(decode_segment).collect();
+
+    sanitize_filename(config, &name.join("-"))
+}
+
+// Url paths come percent-encoded, `My%20File.pdf` is `My File.pdf`
+fn decode_segment(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(target_os = "linux")]
+fn shortcut_filename(config: &Configs, name: &str) -> String {
+    format!("{}.desktop", sanitize_filename(config, name))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn shortcut_filename(config: &Configs, name: &str) -> String {
+    format!("{}.url", sanitize_filename(config, name))
+}
+
+#[cfg(target_os = "linux")]
+fn write_shortcut(path: &Path, name: &str, url: &str) -> Result<()> {
+    let contents = format!(
+        "[Desktop Entry]\nType=Link\nName={}\nURL={}\nIcon=text-html\n",
+        desktop_entry_value(name),
+        desktop_entry_value(url)
+    );
+    Ok(fs::write(path, contents)?)
+}
+
+// Escapes as the Desktop Entry spec has it, so a value can't end its line
+// and start another key
+#[cfg(target_os = "linux")]
+fn desktop_entry_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The format has no escapes, a url spanning lines could add keys
+#[cfg(not(target_os = "linux"))]
+fn write_shortcut(path: &Path, _name: &str, url: &str) -> Result<()> {
+    if url.chars().any(char::is_control) {
+        return Err(eyre::eyre!(
+            "Url '{}' contains control characters",
+            url.escape_debug()
+        ));
+    }
+    let contents = format!("[InternetShortcut]\r\nURL={}\r\n", url);
+    Ok(fs::write(path, contents)?)
+}
+
+// Name the downloaded target after the last segment of its url,
+// falling back to the module name for urls ending in a directory
+fn url_target_filename(config: &Configs, url: &UrlModule) -> String {
+    let segment = reqwest::Url::parse(&url.externalurl)
+        .ok()
+        .and_then(|parsed| {
+            parsed
+                .path_segments()
+                .and_then(|mut segments| segments.next_back().map(decode_segment))
+        })
+        .filter(|segment| !segment.is_empty());
+
+    sanitize_filename(config, &segment.unwrap_or_else(|| url.name.clone()))
+}
+
+// `\w` matches any Unicode letter or digit, accented names are kept
+// unless transliteration to ASCII is enabled in 'config.toml'
+pub fn sanitize_filename(config: &Configs, filename: &str) -> String {
+    let filename = if config.output.transliterate_filenames {
+        deunicode(filename)
+    } else {
+        filename.to_string()
+    };
+
     let re = Regex::new(r"[^\w\.\-]").unwrap();
-    let intermediate = re.replace_all(filename, "");
+    let intermediate = re.replace_all(&filename, "");
     let whitespace_and_underscores = Regex::new(r"[\s_]+").unwrap();
     whitespace_and_underscores
         .replace_all(&intermediate, "-")
         .to_string()
 }
 
+/// A downloaded file of removed content and where archiving moves it
+pub struct RemovedDownload {
+    pub course_id: i64,
+    pub filename: String,
+    pub localpath: PathBuf,
+    pub archived_path: PathBuf,
+}
+
+/// Downloaded files of removed content still outside their course's archive.
+/// They go to its `archive/<date>` directory, dated by when the removal was
+/// noticed.
+pub fn removed_downloads(config: &Configs) -> Result<Vec<RemovedDownload>> {
+    let conn = connect_db()?;
+    let mut stmt = conn.prepare(
+        "
+        SELECT
+            Files.filename, Files.localpath, Files.removedat, Sections.courseid
+        FROM
+            Files
+        INNER JOIN
+            Modules ON Files.module_id = Modules.moduleid
+        INNER JOIN
+            Sections ON Modules.section_id = Sections.sectionid
+        WHERE
+            Files.removedat IS NOT NULL AND Files.localpath IS NOT NULL
+    ",
+    )?;
+    let removed = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut downloads = Vec::new();
+    for (filename, localpath, removedat, course_id) in removed {
+        if !config.courses.iter().any(|course| course.id == course_id) {
+            continue;
+        }
+        let archive_dir = config.get_course_dir(course_id).join("archive");
+        let localpath = PathBuf::from(localpath);
+        if localpath.starts_with(&archive_dir) || !localpath.exists() {
+            continue;
+        }
+
+        let date = removedat.get(..10).unwrap_or(&removedat);
+        let archived_path = archive_dir
+            .join(date)
+            .join(localpath.file_name().unwrap_or_default());
+        downloads.push(RemovedDownload {
+            course_id,
+            filename,
+            localpath,
+            archived_path,
+        });
+    }
+    Ok(downloads)
+}
+
+/// Moves the downloaded files of removed content into their course's archive
+pub fn archive_removed_files(config: &Configs) -> Result<()> {
+    for removed in removed_downloads(config)? {
+        let target_path = removed.archived_path.to_str().unwrap();
+        if let Err(e) = create_dir(target_path)
+            .and_then(|_| Ok(fs::rename(&removed.localpath, &removed.archived_path)?))
+        {
+            log::error!(
+                "Failed to archive '{}': {:?}",
+                removed.localpath.display(),
+                e
+            );
+            continue;
+        }
+        log::info!(
+            "Archived removed file '{}' to '{}'",
+            removed.localpath.display(),
+            target_path
+        );
+        update_file_paths_in_db(&removed.filename, target_path)?;
+    }
+
+    Ok(())
+}
+
 pub fn update_file_paths_in_db(filename: &str, localpath: &str) -> Result<()> {
     let conn = connect_db()?;
-    let sql = format!("UPDATE Files SET localpath = ? WHERE filename = ?");
-    conn.execute(sql.as_str(), params![localpath, filename])?;
+    let sql = "UPDATE Files SET localpath = ? WHERE filename = ?";
+    conn.execute(sql, params![localpath, filename])?;
     Ok(())
 }
 
@@ -113,3 +409,50 @@ pub fn get_course_id(filename: &String) -> Result<Option<i64>> {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_names_are_decoded_before_sanitizing() {
+        let config: Configs = toml::from_str(
+            r#"
+            courses = []
+
+            [api]
+            base_url = "moodle.example.com"
+            userid = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            embedded_filename(
+                &config,
+                "https://moodle.example.com/pluginfile.php/12/mod_page/content/My%20File%281%29.pdf"
+            ),
+            "12-mod-page-content-MyFile1.pdf"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn shortcut_values_stay_on_their_line() {
+        let path = std::env::temp_dir().join(format!("moodl-rs-{}.desktop", std::process::id()));
+        write_shortcut(
+            &path,
+            "Notes\nExec=sh\r\nType=Application\t\\",
+            "https://example.org/\nExec=sh",
+        )
+        .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            contents,
+            "[Desktop Entry]\nType=Link\n\
+             Name=Notes\\nExec=sh\\r\\nType=Application\\t\\\\\n\
+             URL=https://example.org/\\nExec=sh\nIcon=text-html\n"
+        );
+    }
+}
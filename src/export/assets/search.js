@@ -0,0 +1,57 @@
+// Client-side search over search-index.js, written by `moodl-rs export html`
+(function () {
+  const input = document.getElementById("search");
+  const results = document.getElementById("search-results");
+  if (!input || !results || !window.SEARCH_INDEX) {
+    return;
+  }
+
+  const entries = window.SEARCH_INDEX.map((entry) => ({
+    entry,
+    haystack: (entry.title + " " + entry.text).toLowerCase(),
+  }));
+
+  function snippet(text, term) {
+    const at = text.toLowerCase().indexOf(term);
+    if (at < 0) {
+      return text.slice(0, 160);
+    }
+    const start = Math.max(0, at - 60);
+    return (start > 0 ? "…" : "") + text.slice(start, start + 160);
+  }
+
+  input.addEventListener("input", () => {
+    const terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.replaceChildren();
+    if (terms.length === 0) {
+      results.hidden = true;
+      return;
+    }
+
+    const matches = entries
+      .filter(({ haystack }) => terms.every((term) => haystack.includes(term)))
+      .slice(0, 50);
+
+    for (const { entry } of matches) {
+      const item = document.createElement("li");
+      const link = document.createElement("a");
+      link.href = entry.href;
+      link.textContent = entry.course + " › " + entry.title;
+      item.append(link);
+
+      if (entry.text) {
+        const text = document.createElement("p");
+        text.textContent = snippet(entry.text, terms[0]);
+        item.append(text);
+      }
+      results.append(item);
+    }
+
+    if (matches.length === 0) {
+      const item = document.createElement("li");
+      item.textContent = "No matches";
+      results.append(item);
+    }
+    results.hidden = false;
+  });
+})();
@@ -0,0 +1,344 @@
+// export/epub.rs
+//
+// EPUB 3 export of a single course, a chapter per section with the
+// downloaded images packaged alongside
+use crate::{
+    export::{
+        html::{render_html, text},
+        CourseExport,
+    },
+    utils::format_date,
+};
+use chrono::Utc;
+use eyre::{eyre, Result, WrapErr};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+const STYLE: &str = "body { line-height: 1.4; }
+h1, h2, h3 { page-break-after: avoid; }
+img { max-width: 100%; }
+pre { white-space: pre-wrap; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #888; padding: 0.2em 0.4em; }
+.due { font-style: italic; }
+";
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A downloaded image packaged into the book
+struct Image {
+    source: PathBuf,
+    href: String,
+    media_type: &'static str,
+}
+
+pub fn export_epub(course: &CourseExport, out_file: &Path, language: Option<&str>) -> Result<()> {
+    if course.sections.is_empty() {
+        return Err(eyre!(
+            "Course {} has no stored content, run fetch first",
+            course.id
+        ));
+    }
+    // Chapters link to packaged images, everything else stays remote
+    let (image_paths, images) = collect_images(course);
+
+    if let Some(parent) = out_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = File::create(out_file)
+        .wrap_err_with(|| format!("Failed to create {}", out_file.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype has to come first and uncompressed for readers to sniff it
+    zip.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER.as_bytes())?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(nav_document(course, language).as_bytes())?;
+
+    for (i, section) in course.sections.iter().enumerate() {
+        let mut body = format!(
+            "<section epub:type=\"chapter\" id=\"section-{}\">\n<h1>{}</h1>\n{}",
+            section.id,
+            text(&section.name),
+            render_html(&section.summary, &image_paths, false)
+        );
+
+        for module in &section.modules {
+            body.push_str(&format!(
+                "<section id=\"module-{}\">\n<h2>{}</h2>\n",
+                module.id,
+                text(&module.name)
+            ));
+            if let Some(assignment) = course.assignments.iter().find(|a| a.cmid == module.id) {
+                if assignment.duedate > 0 {
+                    body.push_str(&format!(
+                        "<p class=\"due\">Due {}</p>\n",
+                        format_date(assignment.duedate)
+                    ));
+                }
+            }
+            if let Some(description) = &module.description {
+                body.push_str(&render_html(description, &image_paths, false));
+            }
+
+            // Only images are packaged, other files are listed by name
+            let files: Vec<String> = module
+                .contents
+                .iter()
+                .flatten()
+                .filter_map(|file| file.filename.as_deref())
+                .map(|name| format!("<li>{}</li>\n", text(name)))
+                .collect();
+            if !files.is_empty() {
+                body.push_str(&format!("<ul class=\"files\">\n{}</ul>\n", files.concat()));
+            }
+            body.push_str("</section>\n");
+        }
+        body.push_str("</section>\n");
+
+        zip.start_file(format!("OEBPS/{}", chapter_href(i)), deflated)?;
+        zip.write_all(xhtml(&section.name, &body, language).as_bytes())?;
+    }
+
+    for image in &images {
+        let bytes = fs::read(&image.source)
+            .wrap_err_with(|| format!("Failed to read {}", image.source.display()))?;
+        zip.start_file(format!("OEBPS/{}", image.href), deflated)?;
+        zip.write_all(&bytes)?;
+    }
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package_document(course, &images, language).as_bytes())?;
+
+    zip.finish()?;
+    log::info!("Exported course {} to EPUB", course.id);
+    Ok(())
+}
+
+fn chapter_href(index: usize) -> String {
+    format!("section-{:03}.xhtml", index + 1)
+}
+
+// Images that have been downloaded and their path in the book, keyed by remote
+// url. Several urls can share a download, each file is packaged once. Files
+// of the same name from different folders get a number to tell them apart.
+fn collect_images(course: &CourseExport) -> (HashMap<String, String>, Vec<Image>) {
+    let mut image_paths = HashMap::new();
+    let mut images: Vec<Image> = Vec::new();
+
+    let mut local_paths: Vec<_> = course.local_paths.iter().collect();
+    local_paths.sort();
+    for (url, path) in local_paths {
+        let (Some(media_type), Some(name)) = (image_media_type(path), path.file_name()) else {
+            continue;
+        };
+        if !path.exists() {
+            continue;
+        }
+
+        let href = match images.iter().find(|image| image.source == *path) {
+            Some(image) => image.href.clone(),
+            None => {
+                let href = unique_href(&images, &name.to_string_lossy());
+                images.push(Image {
+                    source: path.clone(),
+                    href: href.clone(),
+                    media_type,
+                });
+                href
+            }
+        };
+        image_paths.insert(url.clone(), href);
+    }
+
+    images.sort_by(|a, b| a.href.cmp(&b.href));
+    (image_paths, images)
+}
+
+fn unique_href(images: &[Image], name: &str) -> String {
+    let taken = |href: &str| images.iter().any(|image| image.href == href);
+    let href = format!("images/{}", name);
+    if !taken(&href) {
+        return href;
+    }
+
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    (2..)
+        .map(|n| format!("images/{}-{}.{}", stem, n, extension))
+        .find(|href| !taken(href))
+        .unwrap_or(href)
+}
+
+fn image_media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn xhtml(title: &str, body: &str, language: Option<&str>) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"{}>
+<head>
+<meta charset="UTF-8"/>
+<title>{}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        language
+            .map(|language| format!(" lang=\"{0}\" xml:lang=\"{0}\"", text(language)))
+            .unwrap_or_default(),
+        text(title),
+        body
+    )
+}
+
+// Each section is a top level entry, with its modules nested below
+fn nav_document(course: &CourseExport, language: Option<&str>) -> String {
+    let mut toc = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n");
+
+    for (i, section) in course.sections.iter().enumerate() {
+        let chapter = chapter_href(i);
+        toc.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            chapter,
+            text(&section.name)
+        ));
+        if !section.modules.is_empty() {
+            toc.push_str("\n<ol>\n");
+            for module in &section.modules {
+                toc.push_str(&format!(
+                    "<li><a href=\"{}#module-{}\">{}</a></li>\n",
+                    chapter,
+                    module.id,
+                    text(&module.name)
+                ));
+            }
+            toc.push_str("</ol>\n");
+        }
+        toc.push_str("</li>\n");
+    }
+    toc.push_str("</ol>\n</nav>\n");
+
+    xhtml(&course.name, &toc, language)
+}
+
+fn package_document(course: &CourseExport, images: &[Image], language: Option<&str>) -> String {
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+
+    for i in 0..course.sections.len() {
+        manifest.push_str(&format!(
+            "<item id=\"section-{0}\" href=\"{1}\" media-type=\"application/xhtml+xml\"/>\n",
+            i + 1,
+            chapter_href(i)
+        ));
+        spine.push_str(&format!("<itemref idref=\"section-{}\"/>\n", i + 1));
+    }
+
+    for (i, image) in images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            i + 1,
+            text(&image.href),
+            image.media_type
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">urn:moodl-rs:course:{}</dc:identifier>
+<dc:title>{}</dc:title>
+<dc:language>{}</dc:language>
+<meta property="dcterms:modified">{}</meta>
+</metadata>
+<manifest>
+{}</manifest>
+<spine>
+{}</spine>
+</package>
+"#,
+        course.id,
+        text(&course.name),
+        // Required by EPUB 3, `und` is the tag for an undetermined language
+        text(language.unwrap_or("und")),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        manifest,
+        spine
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_of_the_same_name_get_their_own_href() {
+        let downloads = std::env::temp_dir().join(format!("moodl-rs-epub-{}", std::process::id()));
+        let (week1, week2) = (downloads.join("week1"), downloads.join("week2"));
+        fs::create_dir_all(&week1).unwrap();
+        fs::create_dir_all(&week2).unwrap();
+        fs::write(week1.join("image.png"), "1").unwrap();
+        fs::write(week2.join("image.png"), "2").unwrap();
+
+        let url = |n: i32| format!("https://moodle.example/pluginfile.php/{}/image.png", n);
+        let course = CourseExport {
+            id: 1,
+            name: String::from("Test"),
+            slug: String::from("test"),
+            sections: Vec::new(),
+            assignments: Vec::new(),
+            grades: Vec::new(),
+            local_paths: HashMap::from([
+                (url(1), week1.join("image.png")),
+                (url(2), week2.join("image.png")),
+                (url(3), week1.join("image.png")),
+            ]),
+        };
+        let (image_paths, images) = collect_images(&course);
+        fs::remove_dir_all(&downloads).unwrap();
+
+        let hrefs: Vec<&str> = images.iter().map(|image| image.href.as_str()).collect();
+        assert_eq!(hrefs, ["images/image-2.png", "images/image.png"]);
+        assert_eq!(image_paths[&url(1)], "images/image.png");
+        assert_eq!(image_paths[&url(2)], "images/image-2.png");
+        assert_eq!(image_paths[&url(3)], "images/image.png");
+    }
+}
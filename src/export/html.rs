@@ -0,0 +1,443 @@
+// export/html.rs
+//
+// Static site export, one page per course next to an index page. Course
+// text goes through the markdown converter and is rendered back to HTML so
+// Moodle's markup, scripts and styles never reach the site. Links keep only
+// http, https, mailto and relative urls.
+use crate::{
+    export::CourseExport,
+    parser::{format_percentage, html::HtmlConverter, is_variation_of, resolve_local_links},
+    utils::{format_date, plain_text},
+};
+use eyre::{Result, WrapErr};
+use pulldown_cmark::{
+    escape::{escape_href, escape_html},
+    html::push_html,
+    Event, HeadingLevel, Options, Parser, Tag,
+};
+use serde::Serialize;
+use std::{collections::HashMap, fs, path::Path};
+
+const STYLE: &str = include_str!("assets/style.css");
+const SEARCH: &str = include_str!("assets/search.js");
+
+/// An entry of the client-side search index
+#[derive(Serialize)]
+struct SearchEntry {
+    course: String,
+    title: String,
+    href: String,
+    text: String,
+}
+
+pub fn export_site(courses: &[CourseExport], out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir)
+        .wrap_err_with(|| format!("Failed to create {}", out_dir.display()))?;
+    // Downloaded files are stored by absolute path, a relative `--out`
+    // has nothing in common with them to link from
+    let out_dir = &std::path::absolute(out_dir)?;
+
+    let mut index: Vec<SearchEntry> = Vec::new();
+    for course in courses {
+        let local_paths = resolve_local_links(&course.local_paths, out_dir);
+        let page = course_page(course, &local_paths, &mut index);
+        fs::write(out_dir.join(format!("{}.html", course.slug)), page)?;
+        log::info!("Exported course {} to HTML", course.id);
+    }
+
+    fs::write(out_dir.join("index.html"), index_page(courses))?;
+    fs::write(out_dir.join("style.css"), STYLE)?;
+    fs::write(out_dir.join("search.js"), SEARCH)?;
+    // A script rather than JSON, browsers block fetching files over file://
+    fs::write(
+        out_dir.join("search-index.js"),
+        format!(
+            "window.SEARCH_INDEX = {};\n",
+            serde_json::to_string(&index)?
+        ),
+    )?;
+
+    Ok(())
+}
+
+fn index_page(courses: &[CourseExport]) -> String {
+    let mut body = String::from("<h1>Courses</h1>\n<ul class=\"courses\">\n");
+    for course in courses {
+        let files = course
+            .sections
+            .iter()
+            .flat_map(|section| &section.modules)
+            .flat_map(|module| module.contents.iter().flatten())
+            .count();
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> <span class=\"meta\">{} sections, {} files, {} assignments</span></li>\n",
+            href(&format!("{}.html", course.slug)),
+            text(&course.name),
+            course.sections.len(),
+            files,
+            course.assignments.len(),
+        ));
+    }
+    body.push_str("</ul>\n");
+    page("Courses", &body)
+}
+
+fn course_page(
+    course: &CourseExport,
+    local_paths: &HashMap<String, String>,
+    index: &mut Vec<SearchEntry>,
+) -> String {
+    let page_href = format!("{}.html", course.slug);
+    let mut toc = String::from("<nav class=\"toc\"><ul>\n");
+    let mut body = String::new();
+
+    for section in &course.sections {
+        let anchor = format!("section-{}", section.id);
+        toc.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a></li>\n",
+            anchor,
+            text(&section.name)
+        ));
+        body.push_str(&format!(
+            "<section id=\"{}\">\n<h2>{}</h2>\n{}",
+            anchor,
+            text(&section.name),
+            render_html(&section.summary, local_paths, true)
+        ));
+        index.push(SearchEntry {
+            course: course.name.clone(),
+            title: section.name.clone(),
+            href: format!("{}#{}", page_href, anchor),
+            text: plain_text(&section.summary),
+        });
+
+        for module in &section.modules {
+            let anchor = format!("module-{}", module.id);
+            let description = module.description.as_deref().unwrap_or_default();
+            let rendered = render_html(description, local_paths, true);
+
+            body.push_str(&format!("<article id=\"{}\">\n", anchor));
+            if !is_variation_of(&module.name, &plain_text(description)) {
+                body.push_str(&format!("<h3>{}</h3>\n", text(&module.name)));
+            }
+            if let Some(assignment) = course.assignments.iter().find(|a| a.cmid == module.id) {
+                if assignment.duedate > 0 {
+                    body.push_str(&format!(
+                        "<p class=\"due\">Due {}</p>\n",
+                        format_date(assignment.duedate)
+                    ));
+                }
+            }
+            body.push_str(&rendered);
+            body.push_str(&module_files(module, local_paths));
+            body.push_str("</article>\n");
+
+            index.push(SearchEntry {
+                course: course.name.clone(),
+                title: module.name.clone(),
+                href: format!("{}#{}", page_href, anchor),
+                text: plain_text(description),
+            });
+        }
+        body.push_str("</section>\n");
+    }
+
+    if !course.assignments.is_empty() {
+        toc.push_str("<li><a href=\"#assignments\">Assignments</a></li>\n");
+        body.push_str(&assignments_table(course));
+    }
+    if !course.grades.is_empty() {
+        toc.push_str("<li><a href=\"#grades\">Grades</a></li>\n");
+        body.push_str(&grades_table(course));
+    }
+    toc.push_str("</ul></nav>\n");
+
+    page(
+        &course.name,
+        &format!("<h1>{}</h1>\n{}{}", text(&course.name), toc, body),
+    )
+}
+
+fn module_files(
+    module: &crate::models::course::CourseModule,
+    local_paths: &HashMap<String, String>,
+) -> String {
+    let is_link = module.modname.as_deref() == Some("url");
+    let mut files = String::new();
+
+    for file in module.contents.iter().flatten() {
+        if let (Some(name), Some(url)) = (&file.filename, &file.fileurl) {
+            let item = match local_paths.get(url) {
+                Some(path) => format!("<a href=\"{}\">{}</a>", href(path), text(name)),
+                None if !is_safe_url(url) => text(name),
+                None if is_link => format!("<a href=\"{}\">{}</a>", href(url), text(name)),
+                None => format!(
+                    "<a href=\"{}\">{}</a> <em>(not downloaded)</em>",
+                    href(url),
+                    text(name)
+                ),
+            };
+            files.push_str(&format!("<li>{}</li>\n", item));
+        }
+    }
+
+    if files.is_empty() {
+        files
+    } else {
+        format!("<ul class=\"files\">\n{}</ul>\n", files)
+    }
+}
+
+fn assignments_table(course: &CourseExport) -> String {
+    let mut table = String::from(
+        "<section id=\"assignments\">\n<h2>Assignments</h2>\n<table>\n\
+         <thead><tr><th>Assignment</th><th>Opens</th><th>Due</th><th>Cut-off</th></tr></thead>\n<tbody>\n",
+    );
+
+    for assignment in &course.assignments {
+        let placed = course
+            .sections
+            .iter()
+            .flat_map(|section| &section.modules)
+            .any(|module| module.id == assignment.cmid);
+        let name = if placed {
+            format!(
+                "<a href=\"#module-{}\">{}</a>",
+                assignment.cmid,
+                text(&assignment.name)
+            )
+        } else {
+            text(&assignment.name)
+        };
+
+        table.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            name,
+            format_date(assignment.allowsubmissionsfromdate),
+            format_date(assignment.duedate),
+            format_date(assignment.cutoffdate),
+        ));
+    }
+
+    table.push_str("</tbody>\n</table>\n</section>\n");
+    table
+}
+
+fn grades_table(course: &CourseExport) -> String {
+    let mut table = String::from(
+        "<section id=\"grades\">\n<h2>Grades</h2>\n<table>\n\
+         <thead><tr><th>Item Name</th><th>Graded On</th><th>Range</th><th>Grade</th><th>Percent</th></tr></thead>\n<tbody>\n",
+    );
+
+    for grade in &course.grades {
+        let Some(name) = grade.display_name() else {
+            continue;
+        };
+        table.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            text(name),
+            grade
+                .gradedategraded
+                .map(format_date)
+                .unwrap_or_else(|| String::from("N/A")),
+            text(&grade.range()),
+            grade.grade(),
+            format_percentage(grade.percentage()),
+        ));
+    }
+
+    table.push_str("</tbody>\n</table>\n</section>\n");
+    table
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+<link rel="stylesheet" href="style.css">
+</head>
+<body>
+<header>
+<a href="index.html">All courses</a>
+<input id="search" type="search" placeholder="Search courses" autocomplete="off">
+</header>
+<ol id="search-results" hidden></ol>
+<main>
+{}</main>
+<script src="search-index.js"></script>
+<script src="search.js"></script>
+</body>
+</html>
+"#,
+        text(title),
+        body
+    )
+}
+
+/// Moodle HTML rendered through markdown, headings nested below the module's.
+/// The output is also well-formed XHTML. Without `remote_images`, images that
+/// aren't in `local_paths` are turned into links to them.
+pub fn render_html(
+    html: &str,
+    local_paths: &HashMap<String, String>,
+    remote_images: bool,
+) -> String {
+    let markdown = HtmlConverter::markdown(local_paths).convert(html);
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let is_remote = |url: &str| url.contains("://");
+    let events = Parser::new_ext(&markdown, options).map(|event| match event {
+        // Scripts and inline documents would run in the site, their text stays
+        Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) if !is_safe_url(&url) => {
+            Event::Text("".into())
+        }
+        Event::End(Tag::Link(_, url, _) | Tag::Image(_, url, _)) if !is_safe_url(&url) => {
+            Event::Text("".into())
+        }
+        Event::Start(Tag::Image(kind, url, title)) if !remote_images && is_remote(&url) => {
+            Event::Start(Tag::Link(kind, url, title))
+        }
+        Event::End(Tag::Image(kind, url, title)) if !remote_images && is_remote(&url) => {
+            Event::End(Tag::Link(kind, url, title))
+        }
+        Event::Start(Tag::Heading(level, id, classes)) => {
+            Event::Start(Tag::Heading(shift_heading(level), id, classes))
+        }
+        Event::End(Tag::Heading(level, id, classes)) => {
+            Event::End(Tag::Heading(shift_heading(level), id, classes))
+        }
+        // The converter only emits these tags, anything else is literal text
+        Event::Html(raw) if raw.trim() == "<br>" => Event::Html("<br />".into()),
+        Event::Html(raw) if is_allowed_tag(&raw) => Event::Html(raw),
+        Event::Html(raw) => Event::Text(raw),
+        event => event,
+    });
+
+    let mut rendered = String::new();
+    push_html(&mut rendered, events);
+    rendered
+}
+
+fn shift_heading(level: HeadingLevel) -> HeadingLevel {
+    HeadingLevel::try_from(level as usize + 3).unwrap_or(HeadingLevel::H6)
+}
+
+/// Whether `url` is relative or uses http, https or mailto. Browsers ignore
+/// tabs and newlines in urls and control characters before them.
+pub fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let url = url.trim_start_matches(|c: char| c <= ' ');
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => ["http", "https", "mailto"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        _ => true,
+    }
+}
+
+fn is_allowed_tag(raw: &str) -> bool {
+    matches!(raw.trim(), "<br>" | "<sup>" | "</sup>" | "<sub>" | "</sub>")
+}
+
+pub fn text(content: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, content).expect("Writing to a String can't fail");
+    escaped
+}
+
+pub fn href(url: &str) -> String {
+    let mut escaped = String::new();
+    escape_href(&mut escaped, url).expect("Writing to a String can't fail");
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::course::{CourseFile, CourseModule, CourseSection};
+    use crate::utils::relative_path;
+
+    #[test]
+    fn renders_only_web_and_mail_links() {
+        let html = r#"<p><a href="JavaScript:alert(1)">Run</a>
+            <a href="data:text/html,<script>alert(1)</script>">Open</a>
+            <a href=" vbscript:msgbox(1)">Show</a>
+            <img src="javascript://x%0Aalert(1)" alt="Chart">
+            <iframe src="DATA:text/html,x" title="Player"></iframe>
+            <a href="https://example.org/">Site</a> <a href="MAILTO:me@example.org">Mail</a>
+            <a href="notes.html">Notes</a></p>"#;
+        let rendered = render_html(html, &HashMap::new(), false);
+
+        for scheme in ["javascript:", "data:", "vbscript:"] {
+            assert!(!rendered.to_lowercase().contains(scheme), "{}", rendered);
+        }
+        for text in ["Run", "Open", "Show", "Chart", "Player"] {
+            assert!(rendered.contains(text), "{}", rendered);
+        }
+        assert!(rendered.contains(r#"<a href="https://example.org/">Site</a>"#));
+        assert!(rendered.contains(r#"<a href="MAILTO:me@example.org">Mail</a>"#));
+        assert!(rendered.contains(r#"<a href="notes.html">Notes</a>"#));
+    }
+
+    #[test]
+    fn links_downloads_from_a_relative_out_dir() {
+        let downloads = std::env::temp_dir().join(format!("moodl-rs-html-{}", std::process::id()));
+        fs::create_dir_all(&downloads).unwrap();
+        let file = downloads.join("slides.pdf");
+        fs::write(&file, "pdf").unwrap();
+        let url = String::from("https://moodle.example/pluginfile.php/1/slides.pdf");
+
+        let course = CourseExport {
+            id: 1,
+            name: String::from("Test"),
+            slug: String::from("test"),
+            sections: vec![CourseSection {
+                id: 1,
+                name: String::from("Week 1"),
+                summary: String::new(),
+                courseid: Some(1),
+                modules: vec![CourseModule {
+                    id: 10,
+                    name: String::from("Slides"),
+                    modname: Some(String::from("resource")),
+                    instance: None,
+                    contextid: None,
+                    description: None,
+                    contents: Some(vec![CourseFile {
+                        filename: Some(String::from("slides.pdf")),
+                        filepath: None,
+                        fileurl: Some(url.clone()),
+                        timemodified: None,
+                        module_id: Some(10),
+                    }]),
+                    section_id: Some(1),
+                    lastfetched: None,
+                }],
+                lastfetched: None,
+            }],
+            assignments: Vec::new(),
+            grades: Vec::new(),
+            local_paths: HashMap::from([(url, file.clone())]),
+        };
+
+        let out_dir = Path::new("target").join(format!("test-site-{}", std::process::id()));
+        export_site(&[course], &out_dir).unwrap();
+        let page = fs::read_to_string(out_dir.join("test.html")).unwrap();
+        let link = relative_path(&std::path::absolute(&out_dir).unwrap(), &file);
+
+        fs::remove_dir_all(&out_dir).unwrap();
+        fs::remove_dir_all(&downloads).unwrap();
+        assert!(link.starts_with("../"));
+        assert!(
+            page.contains(&format!("href=\"{}\"", href(&link))),
+            "{}",
+            page
+        );
+    }
+}
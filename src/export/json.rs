@@ -0,0 +1,64 @@
+// export/json.rs
+//
+// Machine readable export of the stored course tree. The layout is
+// documented in docs/export-schema.org, bump SCHEMA_VERSION on any change
+// that isn't a new optional field.
+use crate::{
+    export::CourseExport,
+    models::{assignments::Assignment, course::CourseSection, grades::GradeItem},
+};
+use chrono::Utc;
+use eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    generated_at: String,
+    courses: Vec<CourseDocument<'a>>,
+}
+
+#[derive(Serialize)]
+struct CourseDocument<'a> {
+    id: i64,
+    shortname: &'a str,
+    sections: &'a [CourseSection],
+    assignments: &'a [Assignment],
+    grades: &'a [GradeItem],
+    /// Downloaded files that exist on disk, remote url to absolute path
+    local_files: BTreeMap<&'a str, String>,
+}
+
+pub fn export_json(courses: &[CourseExport]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&document(courses))? + "\n")
+}
+
+pub fn export_yaml(courses: &[CourseExport]) -> Result<String> {
+    Ok(serde_yaml::to_string(&document(courses))?)
+}
+
+fn document(courses: &[CourseExport]) -> Document<'_> {
+    Document {
+        schema_version: SCHEMA_VERSION,
+        generated_at: Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        courses: courses
+            .iter()
+            .map(|course| CourseDocument {
+                id: course.id,
+                shortname: &course.name,
+                sections: &course.sections,
+                assignments: &course.assignments,
+                grades: &course.grades,
+                local_files: course
+                    .local_paths
+                    .iter()
+                    .filter(|(_, path)| path.exists())
+                    .map(|(url, path)| (url.as_str(), path.display().to_string()))
+                    .collect(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,76 @@
+// export/mod.rs
+//
+// Exports read everything from the database, run `fetch` (and `download`
+// for local file links) first
+pub mod epub;
+pub mod html;
+pub mod json;
+pub mod overview;
+
+use crate::{
+    db::connect_db,
+    downloader::{expected_local_paths, sanitize_filename},
+    models::{
+        assignments::{retrieve_course_assignments, Assignment},
+        configs::Configs,
+        course::{retrieve_course_structure, CourseSection},
+        embedded::retrieve_course_embedded_files,
+        grades::{retrieve_course_grades, GradeItem},
+    },
+};
+use eyre::{eyre, Result};
+use rusqlite::Connection;
+use std::{collections::HashMap, path::PathBuf};
+
+/// A tracked course with everything stored for it
+pub struct CourseExport {
+    pub id: i64,
+    pub name: String,
+    /// File name stem for pages generated for the course
+    pub slug: String,
+    pub sections: Vec<CourseSection>,
+    pub assignments: Vec<Assignment>,
+    pub grades: Vec<GradeItem>,
+    /// Where downloaded files are saved, keyed by remote url
+    pub local_paths: HashMap<String, PathBuf>,
+}
+
+pub fn load_courses(config: &Configs) -> Result<Vec<CourseExport>> {
+    let mut conn = connect_db()?;
+    config
+        .courses
+        .iter()
+        .map(|course| load_course(&mut conn, config, course.id))
+        .collect()
+}
+
+pub fn load_course(
+    conn: &mut Connection,
+    config: &Configs,
+    course_id: i64,
+) -> Result<CourseExport> {
+    if !config.courses.iter().any(|course| course.id == course_id) {
+        return Err(eyre!(
+            "Course {} is not tracked in 'config.toml'",
+            course_id
+        ));
+    }
+
+    let sections = retrieve_course_structure(conn, course_id)?;
+    let embedded = retrieve_course_embedded_files(conn, course_id)?;
+    let local_paths = expected_local_paths(config, course_id, &sections, &embedded);
+    let name = config
+        .get_course_name(course_id)
+        .cloned()
+        .unwrap_or_else(|| course_id.to_string());
+
+    Ok(CourseExport {
+        id: course_id,
+        slug: sanitize_filename(config, &name),
+        name,
+        sections,
+        assignments: retrieve_course_assignments(conn, course_id)?,
+        grades: retrieve_course_grades(conn, course_id)?,
+        local_paths,
+    })
+}
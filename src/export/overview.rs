@@ -0,0 +1,178 @@
+// export/overview.rs
+//
+// Standing in every tracked course at a glance: the totals of Moodle's grade
+// overview report next to counts over the stored grade items.
+use crate::{
+    db::connect_db,
+    models::{
+        configs::Configs,
+        grades::{projected_percentage, retrieve_course_grades, retrieve_course_total},
+    },
+    parser::format_percentage,
+    utils::format_date,
+};
+use chrono::Utc;
+use eyre::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Documented in docs/export-schema.org apart from the course export, bump
+// OVERVIEW_SCHEMA_VERSION on any change to the overview's shape
+pub const OVERVIEW_SCHEMA_VERSION: u32 = 1;
+
+pub const OVERVIEW_HEADER: [&str; 7] = [
+    "Course", "Total", "Standing", "Rank", "Graded", "Ungraded", "Latest",
+];
+
+#[derive(Serialize)]
+pub struct CourseOverview {
+    pub id: i64,
+    pub shortname: String,
+    /// Course total as Moodle shows it, or as the stored course total item has it
+    pub total: Option<String>,
+    /// In percent over the graded items, see `projected_percentage`
+    pub standing: Option<f64>,
+    pub rank: Option<i64>,
+    pub graded: usize,
+    pub ungraded: usize,
+    pub latest: Option<LatestGrade>,
+}
+
+/// The most recently graded item of a course
+#[derive(Serialize)]
+pub struct LatestGrade {
+    pub name: String,
+    pub grade: String,
+    pub gradedategraded: i64,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    generated_at: String,
+    courses: &'a [CourseOverview],
+}
+
+pub fn load_overview(config: &Configs) -> Result<Vec<CourseOverview>> {
+    let mut conn = connect_db()?;
+    let mut overview = Vec::new();
+
+    for course in &config.courses {
+        let grades = retrieve_course_grades(&mut conn, course.id)?;
+        let reported = retrieve_course_total(&mut conn, course.id)?;
+        let items: Vec<_> = grades.iter().filter(|grade| !grade.is_total()).collect();
+
+        let total = reported
+            .as_ref()
+            .and_then(|total| total.grade.clone())
+            .filter(|grade| !grade.trim().is_empty() && grade.trim() != "-")
+            .or_else(|| {
+                grades
+                    .iter()
+                    .find(|grade| grade.itemtype.as_deref() == Some("course"))
+                    .filter(|grade| grade.graderaw.is_some())
+                    .map(|grade| grade.grade())
+            });
+        let latest = items
+            .iter()
+            .filter(|grade| grade.graderaw.is_some())
+            .filter_map(|grade| Some((grade, grade.gradedategraded?)))
+            .max_by_key(|(_, graded)| *graded)
+            .map(|(grade, graded)| LatestGrade {
+                name: grade.display_name().unwrap_or_default().to_string(),
+                grade: grade.grade(),
+                gradedategraded: graded,
+            });
+
+        overview.push(CourseOverview {
+            id: course.id,
+            shortname: course
+                .shortname
+                .clone()
+                .unwrap_or_else(|| course.id.to_string()),
+            total,
+            standing: projected_percentage(&grades, &HashMap::new()),
+            rank: reported.and_then(|total| total.rank),
+            graded: items
+                .iter()
+                .filter(|grade| grade.graderaw.is_some())
+                .count(),
+            ungraded: items
+                .iter()
+                .filter(|grade| grade.graderaw.is_none())
+                .count(),
+            latest,
+        });
+    }
+    Ok(overview)
+}
+
+/// One row per course, in the order of `OVERVIEW_HEADER`
+pub fn overview_rows(overview: &[CourseOverview]) -> Vec<[String; 7]> {
+    overview
+        .iter()
+        .map(|course| {
+            [
+                course.shortname.clone(),
+                course.total.clone().unwrap_or_else(|| String::from("N/A")),
+                format_percentage(course.standing),
+                course
+                    .rank
+                    .map_or_else(|| String::from("N/A"), |rank| rank.to_string()),
+                course.graded.to_string(),
+                course.ungraded.to_string(),
+                course.latest.as_ref().map_or_else(
+                    || String::from("N/A"),
+                    |latest| {
+                        format!(
+                            "{} {} on {}",
+                            latest.name,
+                            latest.grade,
+                            format_date(latest.gradedategraded)
+                        )
+                    },
+                ),
+            ]
+        })
+        .collect()
+}
+
+pub fn overview_markdown(overview: &[CourseOverview]) -> String {
+    let rows: Vec<[String; 7]> = overview_rows(overview)
+        .into_iter()
+        .map(|row| row.map(|cell| cell.replace('|', "\\|")))
+        .collect();
+    let mut widths = OVERVIEW_HEADER.map(|title| title.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: [&str; 7]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("| {} |\n", padded.join(" | "))
+    };
+
+    let mut markdown = String::from("# Grade overview\n\n");
+    markdown.push_str(&line(OVERVIEW_HEADER));
+    let dashes: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+    markdown.push_str(&format!("|{}|\n", dashes.join("|")));
+    for row in &rows {
+        markdown.push_str(&line(row.each_ref().map(String::as_str)));
+    }
+    markdown
+}
+
+pub fn overview_json(overview: &[CourseOverview]) -> Result<String> {
+    let document = Document {
+        schema_version: OVERVIEW_SCHEMA_VERSION,
+        generated_at: Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        courses: overview,
+    };
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
@@ -1,9 +1,12 @@
 // main.rs
 //
 mod commands;
+mod credentials;
 mod db;
 mod downloader;
+mod export;
 mod models;
+mod notifier;
 mod parser;
 mod ui;
 mod utils;
@@ -11,48 +14,98 @@ mod ws;
 
 use crate::{
     commands::{
-        command::{Command, DefaultCommand},
+        changes::ChangesCommand,
+        command::{AllProfilesCommand, Command, DefaultCommand},
         download::DownloadCommand,
+        export::ExportCommand,
         fetch::FetchCommand,
+        grades::GradesCommand,
         init::InitCommand,
         parse::ParseCommand,
+        todo::TodoCommand,
+        watch::WatchCommand,
     },
     db::*,
     models::{configs::*, courses::*},
-    ui::prompt::*,
+    ui::{cli::Cli, prompt::*},
     utils::*,
     ws::*,
 };
+use clap::Parser;
 use eyre::Result;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let offline = cli.offline();
     setup_logger().expect("Failed to initialize logging");
-    initialize_db()?;
     let skin = make_skin();
     let mut config = Configs::new()?;
-    let command_enum = prompt_command(&skin)?;
+    config.select_profile(cli.profile())?;
+    use_database(config.database_path())?;
+    let command_enum = match cli.user_command() {
+        Some(command) => command,
+        None => prompt_command(&skin)?,
+    };
     let client;
 
     let mut command: Box<dyn Command>;
     command = match command_enum {
-        UserCommand::Init => Box::new(InitCommand::new(&mut config, &skin)),
-        UserCommand::Fetch => {
-            client = ApiClient::from_config(&config)?;
-            Box::new(FetchCommand::new(client, &config))
+        UserCommand::Init => {
+            if offline {
+                return Err(eyre::eyre!(
+                    "init needs the network, run it without --offline"
+                ));
+            }
+            Box::new(InitCommand::new(&mut config, &skin))
+        }
+        UserCommand::Fetch { full, dry_run } => {
+            client = api_client(&mut config, offline).await?;
+            Box::new(FetchCommand::new(client, &config, full, dry_run))
         }
         UserCommand::Parse => Box::new(ParseCommand::new(&config)),
-        UserCommand::Download => {
-            client = ApiClient::from_config(&config)?;
-            Box::new(DownloadCommand::new(client, &config))
+        UserCommand::Download { dry_run } => {
+            client = api_client(&mut config, offline).await?;
+            Box::new(DownloadCommand::new(client, &config, dry_run))
+        }
+        UserCommand::Default {
+            full,
+            all_profiles: true,
+        } => Box::new(AllProfilesCommand::new(full, offline)),
+        UserCommand::Default { full, .. } => {
+            client = api_client(&mut config, offline).await?;
+            Box::new(DefaultCommand::new(&config, client, full))
         }
-        UserCommand::Default => {
-            client = ApiClient::from_config(&config)?;
-            Box::new(DefaultCommand::new(&config, client))
+        UserCommand::Watch { interval } => {
+            client = api_client(&mut config, offline).await?;
+            Box::new(WatchCommand::new(&config, client, interval))
         }
+        UserCommand::Grades {
+            course,
+            history,
+            what_if,
+            overview,
+        } => Box::new(GradesCommand::new(
+            &config, course, history, what_if, overview,
+        )),
+        UserCommand::Changes { course, last } => {
+            Box::new(ChangesCommand::new(&config, course, last))
+        }
+        UserCommand::Todo { course } => Box::new(TodoCommand::new(&config, course)),
+        UserCommand::Export(target) => Box::new(ExportCommand::new(&config, target)),
     };
 
     command.execute().await?;
 
     Ok(())
 }
+
+// No client is built when offline, commands needing one refuse to run. The
+// token is only looked up here, the keyring may ask to be unlocked.
+async fn api_client(config: &mut Configs, offline: bool) -> Result<Option<ApiClient>> {
+    if offline {
+        return Ok(None);
+    }
+    config.load_token().await?;
+    Ok(Some(ApiClient::from_config(config)?))
+}
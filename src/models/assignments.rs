@@ -1,6 +1,9 @@
 // models/assignments.rs
 //
-use crate::db::{generic_insert, retrieve_param, Insertable, Retrievable};
+use crate::{
+    db::{generic_insert, retrieve_param, Insertable, Retrievable},
+    models::changes::{diff_assignments, insert_changes},
+};
 use eyre::Result;
 use log;
 use rusqlite::{params, Connection, Row, ToSql};
@@ -32,8 +35,25 @@ pub struct Assignment {
     pub courseid: Option<i64>,
 }
 
-pub fn insert_assignments(conn: &mut Connection, mut assignments: Assignments) -> Result<()> {
+pub fn insert_assignments(
+    conn: &mut Connection,
+    mut assignments: Assignments,
+    changeset: i64,
+) -> Result<()> {
+    let mut changes = Vec::new();
+    for course in &assignments.courses {
+        let stored = retrieve_course_assignments(conn, course.id)?;
+        if !stored.is_empty() {
+            changes.append(&mut diff_assignments(
+                course.id,
+                &stored,
+                &course.assignments,
+            ));
+        }
+    }
+
     let tx = conn.transaction()?;
+    insert_changes(&tx, changeset, changes)?;
 
     for course in assignments.courses.iter_mut() {
         for assign in course.assignments.iter_mut() {
@@ -0,0 +1,812 @@
+// models/changes.rs
+//
+// Each fetch opens a change set and records how the course content it
+// stores differs from the rows already in the database. The first fetch of
+// a course only imports it, nothing is recorded then.
+use crate::{
+    db::{generic_insert, retrieve_param, Insertable, Retrievable},
+    models::{
+        assignments::Assignment,
+        course::{CourseFile, CourseModule, CourseSection},
+        grades::GradeItem,
+    },
+    utils::format_date,
+};
+use eyre::{eyre, Result};
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+    Connection, OptionalExtension, Row, ToSql, Transaction,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Section,
+    Module,
+    File,
+    Assignment,
+    Grade,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub changeset: i64,
+    pub courseid: i64,
+    pub entity: Entity,
+    /// Moodle id of the item, the file name for files
+    pub key: String,
+    pub kind: ChangeKind,
+    pub name: String,
+    /// What was modified, or where the item was added
+    pub detail: Option<String>,
+    /// Only set when read from the database
+    pub detectedat: Option<String>,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+impl Entity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Entity::Section => "section",
+            Entity::Module => "module",
+            Entity::File => "file",
+            Entity::Assignment => "assignment",
+            Entity::Grade => "grade",
+        }
+    }
+}
+
+impl ToSql for ChangeKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for ChangeKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "added" => Ok(ChangeKind::Added),
+            "removed" => Ok(ChangeKind::Removed),
+            "modified" => Ok(ChangeKind::Modified),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for Entity {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for Entity {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "section" => Ok(Entity::Section),
+            "module" => Ok(Entity::Module),
+            "file" => Ok(Entity::File),
+            "assignment" => Ok(Entity::Assignment),
+            "grade" => Ok(Entity::Grade),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl Change {
+    fn new(
+        courseid: i64,
+        entity: Entity,
+        key: impl ToString,
+        kind: ChangeKind,
+        name: &str,
+        detail: Option<String>,
+    ) -> Self {
+        Change {
+            changeset: 0,
+            courseid,
+            entity,
+            key: key.to_string(),
+            kind,
+            name: name.to_string(),
+            detail,
+            detectedat: None,
+        }
+    }
+
+    fn modified(
+        courseid: i64,
+        entity: Entity,
+        key: impl ToString,
+        name: &str,
+        details: Vec<String>,
+    ) -> Option<Self> {
+        (!details.is_empty()).then(|| {
+            Change::new(
+                courseid,
+                entity,
+                key,
+                ChangeKind::Modified,
+                name,
+                Some(details.join(", ")),
+            )
+        })
+    }
+}
+
+/// Starts the change set of a fetch
+pub fn begin_changeset(conn: &Connection) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO ChangeSets (createdat) VALUES (CURRENT_TIMESTAMP)",
+        (),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Stores `changes` as part of `changeset`. Deleted content stays in the
+/// database, so a removal is only recorded the first time it is seen.
+pub fn insert_changes(tx: &Transaction, changeset: i64, changes: Vec<Change>) -> Result<usize> {
+    let mut inserted = 0;
+
+    for mut change in changes {
+        if change.kind == ChangeKind::Removed {
+            let last_kind: Option<ChangeKind> = tx
+                .query_row(
+                    "SELECT kind FROM Changes WHERE courseid = ?1 AND entity = ?2 AND key = ?3
+                        ORDER BY id DESC LIMIT 1",
+                    params![change.courseid, change.entity, change.key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if last_kind == Some(ChangeKind::Removed) {
+                continue;
+            }
+        }
+
+        change.changeset = changeset;
+        generic_insert(tx, &change)?;
+        inserted += 1;
+    }
+
+    if inserted > 0 {
+        log::info!("Recorded {} changes", inserted);
+    }
+    Ok(inserted)
+}
+
+/// Changes recorded in the last `last` change sets, oldest first
+pub fn retrieve_changes(
+    conn: &mut Connection,
+    last: usize,
+    courseid: Option<i64>,
+) -> Result<Vec<Change>> {
+    let tx = conn.transaction()?;
+    let first: Option<i64> = tx.query_row(
+        "SELECT MIN(id) FROM (SELECT id FROM ChangeSets ORDER BY id DESC LIMIT ?1)",
+        params![last as i64],
+        |row| row.get(0),
+    )?;
+    let first = first.ok_or_else(|| eyre!("Nothing has been fetched yet"))?;
+
+    let changes = retrieve_param(&tx, params![first, courseid])?;
+    tx.commit()?;
+    Ok(changes)
+}
+
+/// Every change recorded for the course, oldest first
+pub fn retrieve_course_changes(conn: &mut Connection, courseid: i64) -> Result<Vec<Change>> {
+    let tx = conn.transaction()?;
+    let changes = retrieve_param(&tx, params![0, courseid])?;
+    tx.commit()?;
+    Ok(changes)
+}
+
+impl Insertable for Change {
+    fn insert_query() -> &'static str {
+        "INSERT INTO Changes (changeset, courseid, entity, key, kind, name, detail, detectedat)
+            VALUES (:changeset, :courseid, :entity, :key, :kind, :name, :detail, CURRENT_TIMESTAMP)"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![
+            (":changeset", &self.changeset),
+            (":courseid", &self.courseid),
+            (":entity", &self.entity),
+            (":key", &self.key),
+            (":kind", &self.kind),
+            (":name", &self.name),
+            (":detail", &self.detail),
+        ]
+    }
+}
+
+impl Retrievable for Change {
+    fn select_query() -> &'static str {
+        "SELECT changeset, courseid, entity, key, kind, name, detail, detectedat
+            FROM Changes WHERE changeset >= ?1 AND (?2 IS NULL OR courseid = ?2)
+            ORDER BY id"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT changeset, courseid, entity, key, kind, name, detail, detectedat
+            FROM Changes ORDER BY id"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Change {
+            changeset: row.get("changeset")?,
+            courseid: row.get("courseid")?,
+            entity: row.get("entity")?,
+            key: row.get("key")?,
+            kind: row.get("kind")?,
+            name: row.get("name")?,
+            detail: row.get("detail")?,
+            detectedat: row.get("detectedat")?,
+        })
+    }
+}
+
+/// Sections, modules and files of a course as fetched, against `old` as stored
+pub fn diff_sections(courseid: i64, old: &[CourseSection], new: &[CourseSection]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for section in new {
+        match old.iter().find(|old| old.id == section.id) {
+            None => changes.push(Change::new(
+                courseid,
+                Entity::Section,
+                section.id,
+                ChangeKind::Added,
+                &section.name,
+                None,
+            )),
+            Some(old) => {
+                let mut details = Vec::new();
+                if old.name != section.name {
+                    details.push(format!("renamed from \"{}\"", old.name));
+                }
+                if old.summary != section.summary {
+                    details.push("summary changed".to_string());
+                }
+                changes.extend(Change::modified(
+                    courseid,
+                    Entity::Section,
+                    section.id,
+                    &section.name,
+                    details,
+                ));
+            }
+        }
+    }
+    for section in old.iter().filter(|old| !new.iter().any(|s| s.id == old.id)) {
+        changes.push(Change::new(
+            courseid,
+            Entity::Section,
+            section.id,
+            ChangeKind::Removed,
+            &section.name,
+            None,
+        ));
+    }
+
+    // Modules can move between sections, they are matched across the course
+    let (old_modules, new_modules) = (modules(old), modules(new));
+
+    for section in new {
+        for module in &section.modules {
+            match old_modules.get(&module.id) {
+                None => changes.push(Change::new(
+                    courseid,
+                    Entity::Module,
+                    module.id,
+                    ChangeKind::Added,
+                    &module.name,
+                    Some(format!("in {}", section.name)),
+                )),
+                Some((old, old_section)) => {
+                    let mut details = Vec::new();
+                    if old.name != module.name {
+                        details.push(format!("renamed from \"{}\"", old.name));
+                    }
+                    if old.description != module.description {
+                        details.push("description changed".to_string());
+                    }
+                    if *old_section != section.name {
+                        details.push(format!("moved from {}", old_section));
+                    }
+                    changes.extend(Change::modified(
+                        courseid,
+                        Entity::Module,
+                        module.id,
+                        &module.name,
+                        details,
+                    ));
+                }
+            }
+        }
+    }
+    for section in old {
+        for module in &section.modules {
+            if !new_modules.contains_key(&module.id) {
+                changes.push(Change::new(
+                    courseid,
+                    Entity::Module,
+                    module.id,
+                    ChangeKind::Removed,
+                    &module.name,
+                    Some(format!("from {}", section.name)),
+                ));
+            }
+        }
+    }
+
+    // Files are stored by name, so they are matched by name as well. Files of
+    // added or removed modules are covered by the module.
+    let (old_files, new_files) = (files(old), files(new));
+
+    for (name, (file, module)) in &new_files {
+        if !old_modules.contains_key(&module.id) {
+            continue;
+        }
+        match old_files.get(name) {
+            None => changes.push(Change::new(
+                courseid,
+                Entity::File,
+                name,
+                ChangeKind::Added,
+                name,
+                Some(format!("in {}", module.name)),
+            )),
+            Some((old, _)) if old.timemodified != file.timemodified => changes.push(Change::new(
+                courseid,
+                Entity::File,
+                name,
+                ChangeKind::Modified,
+                name,
+                Some(match file.timemodified {
+                    Some(time) => format!("updated {}", change_date(time)),
+                    None => "updated".to_string(),
+                }),
+            )),
+            Some(_) => {}
+        }
+    }
+    for (name, (_, module)) in &old_files {
+        if new_modules.contains_key(&module.id) && !new_files.contains_key(name) {
+            changes.push(Change::new(
+                courseid,
+                Entity::File,
+                name,
+                ChangeKind::Removed,
+                name,
+                Some(format!("from {}", module.name)),
+            ));
+        }
+    }
+
+    changes
+}
+
+/// Assignments of a course as fetched, against `old` as stored
+pub fn diff_assignments(courseid: i64, old: &[Assignment], new: &[Assignment]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for assignment in new {
+        let Some(old) = old.iter().find(|old| old.cmid == assignment.cmid) else {
+            let detail = (assignment.duedate > 0)
+                .then(|| format!("due {}", change_date(assignment.duedate)));
+            changes.push(Change::new(
+                courseid,
+                Entity::Assignment,
+                assignment.cmid,
+                ChangeKind::Added,
+                &assignment.name,
+                detail,
+            ));
+            continue;
+        };
+
+        let mut details = Vec::new();
+        if old.name != assignment.name {
+            details.push(format!("renamed from \"{}\"", old.name));
+        }
+        if old.duedate != assignment.duedate {
+            details.push(format!(
+                "due {} → {}",
+                change_date(old.duedate),
+                change_date(assignment.duedate)
+            ));
+        }
+        if old.cutoffdate != assignment.cutoffdate {
+            details.push(format!(
+                "cut off {} → {}",
+                change_date(old.cutoffdate),
+                change_date(assignment.cutoffdate)
+            ));
+        }
+        if old.allowsubmissionsfromdate != assignment.allowsubmissionsfromdate {
+            details.push(format!(
+                "opens {} → {}",
+                change_date(old.allowsubmissionsfromdate),
+                change_date(assignment.allowsubmissionsfromdate)
+            ));
+        }
+        if old.intro != assignment.intro {
+            details.push("description changed".to_string());
+        }
+        changes.extend(Change::modified(
+            courseid,
+            Entity::Assignment,
+            assignment.cmid,
+            &assignment.name,
+            details,
+        ));
+    }
+    for assignment in old
+        .iter()
+        .filter(|old| !new.iter().any(|a| a.cmid == old.cmid))
+    {
+        changes.push(Change::new(
+            courseid,
+            Entity::Assignment,
+            assignment.cmid,
+            ChangeKind::Removed,
+            &assignment.name,
+            None,
+        ));
+    }
+
+    changes
+}
+
+/// Grade items of a course as fetched, against `old` as stored
+pub fn diff_grades(courseid: i64, old: &[GradeItem], new: &[GradeItem]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    // Items without a name are category totals, they follow the others
+    for item in new.iter().filter(|item| item.itemname.is_some()) {
+        let name = item.itemname.as_deref().unwrap_or_default();
+        let Some(old) = old.iter().find(|old| old.id == item.id) else {
+            changes.push(Change::new(
+                courseid,
+                Entity::Grade,
+                item.id,
+                ChangeKind::Added,
+                name,
+                item.graderaw.map(|_| format!("grade {}", item.grade())),
+            ));
+            continue;
+        };
+
+        let mut details = Vec::new();
+        if old.graderaw != item.graderaw {
+            details.push(format!("grade {} → {}", old.grade(), item.grade()));
+        } else if old.gradedategraded != item.gradedategraded && item.gradedategraded.is_some() {
+            details.push("regraded".to_string());
+        }
+        if old.feedback != item.feedback {
+            details.push(if old.feedback.is_none() {
+                "feedback added".to_string()
+            } else {
+                "feedback changed".to_string()
+            });
+        }
+        changes.extend(Change::modified(
+            courseid,
+            Entity::Grade,
+            item.id,
+            name,
+            details,
+        ));
+    }
+    for item in old.iter().filter(|old| !new.iter().any(|i| i.id == old.id)) {
+        if let Some(name) = &item.itemname {
+            changes.push(Change::new(
+                courseid,
+                Entity::Grade,
+                item.id,
+                ChangeKind::Removed,
+                name,
+                None,
+            ));
+        }
+    }
+
+    changes
+}
+
+// Modules by id, with the name of their section
+fn modules(sections: &[CourseSection]) -> HashMap<i64, (&CourseModule, &str)> {
+    sections
+        .iter()
+        .flat_map(|section| {
+            section
+                .modules
+                .iter()
+                .map(move |module| (module.id, (module, section.name.as_str())))
+        })
+        .collect()
+}
+
+// Files by name, with their module
+fn files(sections: &[CourseSection]) -> BTreeMap<&str, (&CourseFile, &CourseModule)> {
+    sections
+        .iter()
+        .flat_map(|section| &section.modules)
+        .flat_map(|module| {
+            module
+                .contents
+                .iter()
+                .flatten()
+                .filter_map(move |file| Some((file.filename.as_deref()?, (file, module))))
+        })
+        .collect()
+}
+
+// Dates in change details read "none" when not set
+fn change_date(timestamp: i64) -> String {
+    if timestamp <= 0 {
+        return "none".to_string();
+    }
+    format_date(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(id: i64, name: &str, files: &[(&str, i64)]) -> CourseModule {
+        CourseModule {
+            id,
+            name: name.to_string(),
+            modname: Some("resource".to_string()),
+            instance: None,
+            contextid: None,
+            description: None,
+            contents: Some(
+                files
+                    .iter()
+                    .map(|(name, time)| CourseFile {
+                        filename: Some(name.to_string()),
+                        filepath: None,
+                        fileurl: Some(format!("https://moodle.example.edu/{}", name)),
+                        timemodified: Some(*time),
+                        module_id: Some(id),
+                    })
+                    .collect(),
+            ),
+            section_id: None,
+            lastfetched: None,
+        }
+    }
+
+    fn section(id: i64, name: &str, modules: Vec<CourseModule>) -> CourseSection {
+        CourseSection {
+            id,
+            name: name.to_string(),
+            summary: String::new(),
+            courseid: Some(1),
+            modules,
+            lastfetched: None,
+        }
+    }
+
+    fn assignment(cmid: i64, name: &str, duedate: i64) -> Assignment {
+        Assignment {
+            id: cmid,
+            cmid,
+            course: 1,
+            name: name.to_string(),
+            duedate,
+            allowsubmissionsfromdate: 0,
+            timemodified: 0,
+            cutoffdate: 0,
+            intro: None,
+            courseid: Some(1),
+        }
+    }
+
+    fn grade(id: i64, name: Option<&str>, graderaw: Option<f64>) -> GradeItem {
+        GradeItem {
+            id,
+            itemname: name.map(str::to_string),
+            itemtype: Some(if name.is_some() { "mod" } else { "course" }.to_string()),
+            itemmodule: None,
+            iteminstance: id,
+            itemnumber: None,
+            idnumber: None,
+            categoryid: None,
+            cmid: None,
+            graderaw,
+            gradedatesubmitted: None,
+            gradedategraded: None,
+            grademin: 0.0,
+            grademax: 10.0,
+            feedback: None,
+            courseid: Some(1),
+            weightraw: None,
+            gradeformatted: None,
+            percentageformatted: None,
+            lettergradeformatted: None,
+        }
+    }
+
+    fn describe(changes: Vec<Change>) -> Vec<String> {
+        changes
+            .into_iter()
+            .map(|change| {
+                format!(
+                    "{} {} {} {}",
+                    change.kind.as_str(),
+                    change.entity.as_str(),
+                    change.name,
+                    change.detail.unwrap_or_default()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn assignments_diff() {
+        let old = vec![
+            assignment(10, "Essay", 1700000000),
+            assignment(11, "Lab", 0),
+        ];
+        let mut essay = assignment(10, "Essay", 1700086400);
+        essay.cutoffdate = 1700172800;
+        essay.allowsubmissionsfromdate = 1699900000;
+        let new = vec![
+            essay,
+            assignment(12, "Project", 1700500000),
+            assignment(13, "Reading", 0),
+        ];
+
+        let date = format_date;
+        assert_eq!(
+            describe(diff_assignments(1, &old, &new)),
+            [
+                format!(
+                    "modified assignment Essay due {} → {}, cut off none → {}, opens none → {}",
+                    date(1700000000),
+                    date(1700086400),
+                    date(1700172800),
+                    date(1699900000)
+                ),
+                format!("added assignment Project due {}", date(1700500000)),
+                String::from("added assignment Reading "),
+                String::from("removed assignment Lab "),
+            ]
+        );
+        assert!(diff_assignments(1, &old, &old).is_empty());
+    }
+
+    #[test]
+    fn grades_diff() {
+        let old = vec![
+            grade(1, Some("Quiz"), Some(5.0)),
+            grade(2, Some("Essay"), Some(8.0)),
+            grade(3, Some("Lab"), Some(9.0)),
+            grade(4, None, Some(22.0)),
+            grade(5, Some("Dropped"), None),
+        ];
+        let mut regraded = grade(2, Some("Essay"), Some(8.0));
+        regraded.gradedategraded = Some(1700000000);
+        let mut feedback = grade(3, Some("Lab"), Some(9.0));
+        feedback.feedback = Some(String::from("Well done"));
+        let new = vec![
+            grade(1, Some("Quiz"), Some(7.0)),
+            regraded,
+            feedback,
+            // Totals follow the other items and are left out
+            grade(4, None, Some(24.0)),
+            grade(6, Some("Final"), Some(6.5)),
+        ];
+
+        assert_eq!(
+            describe(diff_grades(1, &old, &new)),
+            [
+                "modified grade Quiz grade 5 → 7",
+                "modified grade Essay regraded",
+                "modified grade Lab feedback added",
+                "added grade Final grade 6.5",
+                "removed grade Dropped ",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_a_removal_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        let removed = || {
+            vec![Change::new(
+                1,
+                Entity::Module,
+                10,
+                ChangeKind::Removed,
+                "Slides",
+                None,
+            )]
+        };
+        let added = || {
+            vec![Change::new(
+                1,
+                Entity::Module,
+                10,
+                ChangeKind::Added,
+                "Slides",
+                None,
+            )]
+        };
+
+        let changesets: Vec<i64> = (0..4).map(|_| begin_changeset(&conn).unwrap()).collect();
+        let tx = conn.transaction().unwrap();
+        assert_eq!(insert_changes(&tx, changesets[0], removed()).unwrap(), 1);
+        assert_eq!(insert_changes(&tx, changesets[1], removed()).unwrap(), 0);
+        // Removed again after coming back
+        assert_eq!(insert_changes(&tx, changesets[2], added()).unwrap(), 1);
+        assert_eq!(insert_changes(&tx, changesets[3], removed()).unwrap(), 1);
+    }
+
+    #[test]
+    fn sections_diff() {
+        let old = vec![
+            section(1, "Week 1", vec![module(10, "Slides", &[("a.pdf", 1)])]),
+            section(2, "Week 2", vec![module(20, "Old quiz", &[])]),
+        ];
+        let new = vec![
+            section(
+                1,
+                "Week 1",
+                vec![
+                    module(10, "Slides", &[("a.pdf", 2), ("b.pdf", 1)]),
+                    module(11, "Lecture 2", &[("c.pdf", 1)]),
+                ],
+            ),
+            section(3, "Week 3", Vec::new()),
+        ];
+
+        let changes: Vec<_> = diff_sections(1, &old, &new)
+            .into_iter()
+            .map(|change| {
+                format!(
+                    "{} {} {} {}",
+                    change.kind.as_str(),
+                    change.entity.as_str(),
+                    change.name,
+                    change.detail.unwrap_or_default()
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "added section Week 3 ",
+                "removed section Week 2 ",
+                "added module Lecture 2 in Week 1",
+                "removed module Old quiz from Week 2",
+                "modified file a.pdf updated 1970-01-01 00:00",
+                "added file b.pdf in Slides",
+            ]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+// models/completion.rs
+//
+// Completion state of the user's activities and courses, as Moodle's
+// completion API reports it.
+use crate::db::{generic_insert, retrieve_param, Insertable, Retrievable};
+use eyre::Result;
+use regex::Regex;
+use rusqlite::{params, Connection, Row, ToSql};
+use {serde::Deserialize, serde::Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActivitiesCompletion {
+    pub statuses: Vec<ActivityCompletion>,
+    warnings: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActivityCompletion {
+    pub cmid: i64,
+    pub modname: Option<String>,
+    pub instance: Option<i64>,
+    /// 0 incomplete, 1 complete, 2 complete with a pass, 3 complete with a fail
+    pub state: i64,
+    pub timecompleted: Option<i64>,
+    /// 0 not tracked, 1 marked by the user, 2 completed by conditions
+    pub tracking: i64,
+    #[serde(default)]
+    pub courseid: Option<i64>,
+}
+
+impl ActivityCompletion {
+    pub fn is_tracked(&self) -> bool {
+        self.tracking > 0
+    }
+
+    /// Tracked and not done yet, failed activities count as not done
+    pub fn is_open(&self) -> bool {
+        self.is_tracked() && matches!(self.state, 0 | 3)
+    }
+
+    /// `complete`, `incomplete` or `failed`, none when not tracked
+    pub fn label(&self) -> Option<&'static str> {
+        if !self.is_tracked() {
+            return None;
+        }
+        Some(match self.state {
+            1 | 2 => "complete",
+            3 => "failed",
+            _ => "incomplete",
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CourseCompletionResponse {
+    pub completionstatus: CourseCompletion,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CourseCompletion {
+    pub completed: bool,
+    /// 1 when every criterion is needed, 2 when any is enough
+    pub aggregation: i64,
+    /// Only set from the API, activity criteria are stored by cmid
+    #[serde(default, skip_serializing)]
+    pub completions: Vec<CompletionCriterion>,
+    #[serde(default)]
+    pub courseid: Option<i64>,
+}
+
+/// A criterion of course completion, such as completing an activity,
+/// passing a grade or a date passing
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionCriterion {
+    /// 4 for activity completion
+    #[serde(rename = "type")]
+    pub kind: i64,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub details: Option<CriterionDetails>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CriterionDetails {
+    /// HTML, a link to the activity for activity criteria
+    pub criteria: Option<String>,
+}
+
+impl CompletionCriterion {
+    /// The activity an activity criterion requires. Moodle only names it by
+    /// a link to its page, `mod/<type>/view.php?id=<cmid>`.
+    pub fn cmid(&self) -> Option<i64> {
+        if self.kind != 4 {
+            return None;
+        }
+        let criteria = self.details.as_ref()?.criteria.as_deref()?;
+        let re = Regex::new(r#"/mod/\w+/view\.php\?(?:[^"'>]*[&;])?id=(\d+)"#).unwrap();
+        re.captures(criteria)?.get(1)?.as_str().parse().ok()
+    }
+}
+
+/// An activity a course requires for its completion
+#[derive(Debug)]
+pub struct RequiredActivity {
+    pub courseid: i64,
+    pub cmid: i64,
+}
+
+/// Replaces the stored completion of the course's activities
+pub fn insert_activity_completion(
+    conn: &mut Connection,
+    courseid: i64,
+    mut completion: ActivitiesCompletion,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    // Activities no longer reported were removed or stopped being tracked
+    tx.execute(
+        "DELETE FROM ActivityCompletion WHERE courseid = ?1",
+        params![courseid],
+    )?;
+    for status in completion.statuses.iter_mut() {
+        status.courseid = Some(courseid);
+        generic_insert(&tx, status)?;
+    }
+    tx.commit()?;
+    log::info!(
+        "Successfully stored course {} activity completion",
+        courseid
+    );
+    Ok(())
+}
+
+pub fn insert_course_completion(
+    conn: &mut Connection,
+    courseid: i64,
+    mut completion: CourseCompletion,
+) -> Result<()> {
+    completion.courseid = Some(courseid);
+    let tx = conn.transaction()?;
+    generic_insert(&tx, &completion)?;
+    tx.execute(
+        "DELETE FROM RequiredActivities WHERE courseid = ?1",
+        params![courseid],
+    )?;
+    for cmid in completion.completions.iter().filter_map(|c| c.cmid()) {
+        generic_insert(&tx, &RequiredActivity { courseid, cmid })?;
+    }
+    tx.commit()?;
+    log::info!("Successfully stored course {} completion", courseid);
+    Ok(())
+}
+
+impl Insertable for ActivityCompletion {
+    fn insert_query() -> &'static str {
+        "INSERT INTO ActivityCompletion (
+        cmid, courseid, modname, instance, state, timecompleted, tracking, lastfetched)
+        VALUES (:cmid, :courseid, :modname, :instance, :state, :timecompleted, :tracking,
+            CURRENT_TIMESTAMP)
+        ON CONFLICT(cmid) DO UPDATE SET
+            courseid=excluded.courseid,
+            modname=excluded.modname,
+            instance=excluded.instance,
+            state=excluded.state,
+            timecompleted=excluded.timecompleted,
+            tracking=excluded.tracking,
+            lastfetched=excluded.lastfetched"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![
+            (":cmid", &self.cmid),
+            (":courseid", &self.courseid),
+            (":modname", &self.modname),
+            (":instance", &self.instance),
+            (":state", &self.state),
+            (":timecompleted", &self.timecompleted),
+            (":tracking", &self.tracking),
+        ]
+    }
+}
+
+impl Retrievable for ActivityCompletion {
+    fn select_query() -> &'static str {
+        "SELECT cmid, courseid, modname, instance, state, timecompleted, tracking
+            FROM ActivityCompletion WHERE courseid = ?1"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT cmid, courseid, modname, instance, state, timecompleted, tracking
+            FROM ActivityCompletion"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(ActivityCompletion {
+            cmid: row.get("cmid")?,
+            courseid: row.get("courseid")?,
+            modname: row.get("modname")?,
+            instance: row.get("instance")?,
+            state: row.get("state")?,
+            timecompleted: row.get("timecompleted")?,
+            tracking: row.get("tracking")?,
+        })
+    }
+}
+
+impl Insertable for CourseCompletion {
+    fn insert_query() -> &'static str {
+        "INSERT INTO CourseCompletion (courseid, completed, aggregation, lastfetched)
+        VALUES (:courseid, :completed, :aggregation, CURRENT_TIMESTAMP)
+        ON CONFLICT(courseid) DO UPDATE SET
+            completed=excluded.completed,
+            aggregation=excluded.aggregation,
+            lastfetched=excluded.lastfetched"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![
+            (":courseid", &self.courseid),
+            (":completed", &self.completed),
+            (":aggregation", &self.aggregation),
+        ]
+    }
+}
+
+impl Retrievable for CourseCompletion {
+    fn select_query() -> &'static str {
+        "SELECT courseid, completed, aggregation FROM CourseCompletion WHERE courseid = ?1"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT courseid, completed, aggregation FROM CourseCompletion"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(CourseCompletion {
+            courseid: row.get("courseid")?,
+            completed: row.get("completed")?,
+            aggregation: row.get("aggregation")?,
+            completions: Vec::new(),
+        })
+    }
+}
+
+impl Insertable for RequiredActivity {
+    fn insert_query() -> &'static str {
+        "INSERT OR IGNORE INTO RequiredActivities (courseid, cmid) VALUES (:courseid, :cmid)"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![(":courseid", &self.courseid), (":cmid", &self.cmid)]
+    }
+}
+
+impl Retrievable for RequiredActivity {
+    fn select_query() -> &'static str {
+        "SELECT courseid, cmid FROM RequiredActivities WHERE courseid = ?1"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT courseid, cmid FROM RequiredActivities"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(RequiredActivity {
+            courseid: row.get("courseid")?,
+            cmid: row.get("cmid")?,
+        })
+    }
+}
+
+pub fn retrieve_activity_completion(
+    conn: &mut Connection,
+    courseid: i64,
+) -> Result<Vec<ActivityCompletion>> {
+    let tx = conn.transaction()?;
+    let completion: Vec<ActivityCompletion> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(completion)
+}
+
+/// Completion of the course itself, none when it sets no completion criteria
+pub fn retrieve_course_completion(
+    conn: &mut Connection,
+    courseid: i64,
+) -> Result<Option<CourseCompletion>> {
+    let tx = conn.transaction()?;
+    let completion: Vec<CourseCompletion> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(completion.into_iter().next())
+}
+
+/// Activities the course's completion criteria require, by cmid. Empty when
+/// the course requires no activities.
+pub fn retrieve_required_activities(conn: &mut Connection, courseid: i64) -> Result<Vec<i64>> {
+    let tx = conn.transaction()?;
+    let required: Vec<RequiredActivity> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(required.into_iter().map(|activity| activity.cmid).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_activities_are_tracked_and_not_passed() {
+        let json = r#"{"statuses": [
+            {"cmid": 1, "modname": "assign", "instance": 1, "state": 0, "timecompleted": 0, "tracking": 2},
+            {"cmid": 2, "modname": "quiz", "instance": 2, "state": 3, "timecompleted": 0, "tracking": 2},
+            {"cmid": 3, "modname": "page", "instance": 3, "state": 1, "timecompleted": 1, "tracking": 1},
+            {"cmid": 4, "modname": "label", "instance": 4, "state": 0, "timecompleted": 0, "tracking": 0}
+        ], "warnings": []}"#;
+        let completion: ActivitiesCompletion = serde_json::from_str(json).unwrap();
+        let open: Vec<i64> = completion
+            .statuses
+            .iter()
+            .filter(|status| status.is_open())
+            .map(|status| status.cmid)
+            .collect();
+        assert_eq!(open, [1, 2]);
+        let labels: Vec<_> = completion
+            .statuses
+            .iter()
+            .map(|status| status.label())
+            .collect();
+        assert_eq!(
+            labels,
+            [Some("incomplete"), Some("failed"), Some("complete"), None]
+        );
+    }
+
+    #[test]
+    fn stores_the_activities_course_completion_requires() {
+        let json = r#"{"completionstatus": {"completed": false, "aggregation": 1, "completions": [
+            {"type": 4, "title": "Activity completion", "status": "No", "complete": false,
+             "timecompleted": null, "details": {"type": "Activity completion",
+             "criteria": "<a href=\"https://moodle.example/mod/assign/view.php?id=12\">Essay</a>",
+             "requirement": "Marking yourself complete", "status": ""}},
+            {"type": 4, "title": "Activity completion", "status": "Yes", "complete": true,
+             "timecompleted": 1700000000, "details": {"type": "Activity completion",
+             "criteria": "<a href=\"https://moodle.example/mod/quiz/view.php?forceview=1&amp;id=34\">Quiz</a>",
+             "requirement": "Achieving grade", "status": ""}},
+            {"type": 6, "title": "Course grade", "status": "No", "complete": false,
+             "timecompleted": null, "details": {"type": "Course grade",
+             "criteria": "Course grade", "requirement": "50.00%", "status": ""}}
+        ]}, "warnings": []}"#;
+        let response: CourseCompletionResponse = serde_json::from_str(json).unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        insert_course_completion(&mut conn, 1, response.completionstatus).unwrap();
+        assert_eq!(
+            retrieve_required_activities(&mut conn, 1).unwrap(),
+            [12, 34]
+        );
+        assert!(retrieve_required_activities(&mut conn, 2)
+            .unwrap()
+            .is_empty());
+    }
+}
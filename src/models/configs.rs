@@ -1,11 +1,19 @@
 // models/configs.rs
 //
-use crate::utils::{config_dir, create_dir, modify_shortname};
+use crate::{
+    credentials::{load_token, store_token},
+    models::changes::Entity,
+    utils::{config_dir, create_dir, data_dir, home_dir, modify_shortname},
+};
 use {
     config::{Config, File},
     eyre::{Result, WrapErr},
     serde::{Deserialize, Serialize},
-    std::{fs, io, path::Path},
+    std::{
+        collections::BTreeMap,
+        fs, io, mem,
+        path::{Path, PathBuf},
+    },
     termimad::{MadSkin, Question},
     toml,
 };
@@ -13,21 +21,196 @@ use {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Configs {
     pub api: ApiConfig,
+    #[serde(default)]
+    pub links: LinksConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
     pub courses: Vec<CourseConfig>,
+    /// Further Moodle sites or accounts, picked with `--profile`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Name of the profile swapped into `api` and `courses`, see `select_profile`
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Token of the selected profile, see `load_token`
+    #[serde(skip)]
+    token: Option<String>,
+}
+
+/// A Moodle site and account with its own courses and database
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub courses: Vec<CourseConfig>,
+    /// Database file relative to the data directory, `moodl-rs-<name>.db` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiConfig {
     pub base_url: String,
+    /// Plaintext token, only read when none is stored elsewhere, see `credentials`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub token: String,
     pub userid: i64,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinksConfig {
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputConfig {
+    #[serde(default = "default_formats")]
+    pub formats: Vec<OutputFormat>,
+    #[serde(default)]
+    pub strip_emojis: bool,
+    #[serde(default)]
+    pub transliterate_filenames: bool,
+    /// Write the recorded changes of each course to `changes.md`
+    #[serde(default)]
+    pub changelog: bool,
+    /// Move downloaded files of removed content to `archive/<date>/`
+    #[serde(default)]
+    pub archive_removed: bool,
+    /// Language of exported books as a tag like `en` or `de-CH`
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            formats: default_formats(),
+            strip_emojis: false,
+            transliterate_filenames: false,
+            changelog: false,
+            archive_removed: false,
+            language: None,
+        }
+    }
+}
+
+/// Where to send notifications about changes and upcoming deadlines,
+/// see `notifier`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// Kinds of changes worth a notification
+    #[serde(default = "default_notify_changes")]
+    pub changes: Vec<Entity>,
+    /// How long before an assignment's due date to be reminded, like `24h`
+    #[serde(default)]
+    pub reminders: Vec<String>,
+    /// Program and arguments to run with each notification as JSON on stdin
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailConfig>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            changes: default_notify_changes(),
+            reminders: Vec::new(),
+            command: Vec::new(),
+            webhooks: Vec::new(),
+            email: None,
+        }
+    }
+}
+
+impl NotifyConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.command.is_empty() || !self.webhooks.is_empty() || self.email.is_some()
+    }
+}
+
+fn default_notify_changes() -> Vec<Entity> {
+    vec![
+        Entity::Module,
+        Entity::File,
+        Entity::Assignment,
+        Entity::Grade,
+    ]
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+/// Body of the POST, shaped for the service behind the webhook
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The notification as JSON, as a command gets it
+    #[default]
+    Json,
+    /// The message as text with the title in a header
+    Ntfy,
+    /// `title`, `message` and `priority` as JSON
+    Gotify,
+    /// The title and message as the `content` of a Discord message
+    Discord,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub server: String,
+    /// Defaults to the usual port of `tls`
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: EmailTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTls {
+    /// Upgrade a plain connection, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Unencrypted, for a relay on the same machine
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Markdown,
+    Org,
+    /// A note per section and module, for Obsidian or Logseq
+    Vault,
+}
+
+fn default_formats() -> Vec<OutputFormat> {
+    vec![OutputFormat::Markdown]
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CourseConfig {
     pub id: i64,
     pub shortname: Option<String>,
     pub path: Option<String>,
+    /// MiniJinja template for the course markdown, relative to the config directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
 }
 
 impl From<&crate::Course> for CourseConfig {
@@ -35,14 +218,16 @@ impl From<&crate::Course> for CourseConfig {
         if let Some(ref shortname) = course.shortname {
             CourseConfig {
                 id: course.id,
-                shortname: Some(modify_shortname(&shortname)),
+                shortname: Some(modify_shortname(shortname)),
                 path: None,
+                template: None,
             }
         } else {
             CourseConfig {
                 id: course.id,
                 shortname: course.shortname.clone(),
                 path: None,
+                template: None,
             }
         }
     }
@@ -61,6 +246,68 @@ impl Configs {
         Ok(s.try_deserialize()?)
     }
 
+    /// Swaps the named profile's site, account and courses in for the
+    /// default ones, so the rest of moodl-rs only ever sees `api` and `courses`
+    pub fn select_profile(&mut self, name: Option<&str>) -> Result<()> {
+        self.swap_profile();
+        self.profile = None;
+        let Some(name) = name else {
+            return Ok(());
+        };
+        if !self.profiles.contains_key(name) {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(eyre::eyre!(
+                "No profile '{}' in 'config.toml', there are: {}",
+                name,
+                if names.is_empty() {
+                    String::from("none")
+                } else {
+                    names.join(", ")
+                }
+            ));
+        }
+        self.profile = Some(name.to_string());
+        self.swap_profile();
+        Ok(())
+    }
+
+    fn swap_profile(&mut self) {
+        if let Some(profile) = self
+            .profile
+            .as_ref()
+            .and_then(|name| self.profiles.get_mut(name))
+        {
+            mem::swap(&mut self.api, &mut profile.api);
+            mem::swap(&mut self.courses, &mut profile.courses);
+        }
+    }
+
+    /// Looks up the selected profile's token, run after `select_profile` and
+    /// only where an `ApiClient` is built
+    pub async fn load_token(&mut self) -> Result<()> {
+        self.token = load_token(self.profile.as_deref(), &self.api.token)
+            .await?
+            .map(|(token, _)| token);
+        Ok(())
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Database of the selected profile
+    pub fn database_path(&self) -> PathBuf {
+        match &self.profile {
+            Some(name) => data_dir().join(
+                self.profiles[name]
+                    .database
+                    .clone()
+                    .unwrap_or_else(|| format!("moodl-rs-{}.db", name)),
+            ),
+            None => data_dir().join("moodl-rs.db"),
+        }
+    }
+
     pub fn get_course_name(&self, id: i64) -> Option<&String> {
         self.courses.iter().find_map(|course| {
             if course.id == id {
@@ -81,10 +328,34 @@ impl Configs {
         })
     }
 
+    /// The course's own template, see `parser::template`
+    pub fn get_course_template(&self, id: i64) -> Option<PathBuf> {
+        self.courses
+            .iter()
+            .find(|course| course.id == id)
+            .and_then(|course| course.template.as_ref())
+            .map(|template| config_dir().join(template))
+    }
+
+    /// Directory course files are saved to, `~/<path>/<shortname>`
+    pub fn get_course_dir(&self, id: i64) -> PathBuf {
+        let mut course_dir = home_dir();
+        if let Some(path) = self.get_course_path(id) {
+            course_dir = course_dir.join(path);
+        }
+        if let Some(name) = self.get_course_name(id) {
+            course_dir = course_dir.join(name);
+        }
+        course_dir
+    }
+
     pub fn write_to_file(&mut self) -> Result<()> {
-        let data = toml::to_string(self).wrap_err("Failed to serialize config to TOML format")?;
+        // The selected profile is written back to its own table
+        self.swap_profile();
+        let data = toml::to_string(self).wrap_err("Failed to serialize config to TOML format");
+        self.swap_profile();
         let config_file = config_dir().join("config.toml");
-        fs::write(config_file, data).wrap_err("Failed to write updated config to file")
+        fs::write(config_file, data?).wrap_err("Failed to write updated config to file")
     }
 
     pub fn write_baseurl(&mut self, baseurl: &String) -> Result<()> {
@@ -99,9 +370,11 @@ impl Configs {
         self.write_to_file()
     }
 
-    pub fn write_token(&mut self, token: &String) -> Result<()> {
-        self.api.token = token.to_string();
-        log::info!("Wrote token {} to 'config.toml'", token);
+    pub async fn write_token(&mut self, token: &str) -> Result<()> {
+        let source = store_token(self.profile.as_deref(), token).await?;
+        self.token = Some(token.to_string());
+        self.api.token.clear();
+        log::info!("Wrote token to {}", source);
         self.write_to_file()
     }
 
@@ -112,12 +385,10 @@ impl Configs {
     }
 
     pub async fn prompt_config(&mut self, skin: &MadSkin) -> Result<()> {
-        let question = format!(
-            "Would you like to configure your moodle url and API token now?
-    You will only have to do this once.",
-        );
+        let question = "Would you like to configure your moodle url and API token now?
+    You will only have to do this once.";
 
-        let mut q = Question::new(&question);
+        let mut q = Question::new(question);
         q.add_answer('y', "**Y**es, configure now");
         q.add_answer('n', "**N**o, skip and configure manually");
         q.set_default('y');
@@ -126,7 +397,8 @@ impl Configs {
 
         if answer == "y" {
             self.write_baseurl(&Self::prompt_user_url().wrap_err("Invalid URL")?)?;
-            self.write_token(&Self::prompt_user_token().wrap_err("Invalid token")?)?;
+            self.write_token(&Self::prompt_user_token().wrap_err("Invalid token")?)
+                .await?;
         }
         Ok(())
     }
@@ -155,10 +427,55 @@ pub fn init_config_file() -> Result<(), eyre::Report> {
 
     if !Path::new(&dest_path).exists() {
         let config_template = include_str!("../config.toml");
-        fs::write(&dest_path, config_template)
+        fs::write(dest_path, config_template)
             .wrap_err("Failed to write example config to user's config directory")?;
         log::info!("Created example config at {}", dest_path);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selected_profile_is_written_back_to_its_table() {
+        let mut config: Configs = toml::from_str(
+            r#"
+            [api]
+            base_url = "one.example.com"
+            token = "one"
+            userid = 1
+
+            [[courses]]
+            id = 10
+            shortname = "ONE"
+
+            [profiles.college.api]
+            base_url = "two.example.com"
+            token = "two"
+            userid = 2
+
+            [[profiles.college.courses]]
+            id = 20
+            shortname = "TWO"
+            "#,
+        )
+        .unwrap();
+
+        config.select_profile(Some("college")).unwrap();
+        assert_eq!(config.api.base_url, "two.example.com");
+        assert_eq!(config.get_course_name(20).unwrap(), "TWO");
+        assert!(config.database_path().ends_with("moodl-rs-college.db"));
+        assert!(config.select_profile(Some("work")).is_err());
+
+        config.select_profile(Some("college")).unwrap();
+        config.api.userid = 3;
+        config.swap_profile();
+        let written: Configs = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(written.api.base_url, "one.example.com");
+        assert_eq!(written.profiles["college"].api.userid, 3);
+        assert_eq!(written.profiles["college"].courses[0].id, 20);
+    }
+}
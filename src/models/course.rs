@@ -1,8 +1,11 @@
 // models/course.rs
 //
-use crate::db::{generic_insert, generic_retrieve, retrieve_param, Insertable, Retrievable};
+use crate::{
+    db::{generic_insert, generic_retrieve, retrieve_param, Insertable, Retrievable},
+    models::changes::{diff_sections, insert_changes},
+};
 use eyre::Result;
-use rusqlite::{params, Connection, Row, ToSql};
+use rusqlite::{params, Connection, Row, ToSql, Transaction};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -13,17 +16,24 @@ pub struct CourseSection {
     pub summary: String,
     pub courseid: Option<i64>,
     pub modules: Vec<CourseModule>,
+    /// When the section was last stored, only set when read from the database
+    #[serde(default)]
+    pub lastfetched: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CourseModule {
     pub id: i64,
     pub name: String,
+    pub modname: Option<String>,
     pub instance: Option<i64>,
     pub contextid: Option<i64>,
     pub description: Option<String>,
     pub contents: Option<Vec<CourseFile>>,
     pub section_id: Option<i64>,
+    /// When the module was last stored, only set when read from the database
+    #[serde(default)]
+    pub lastfetched: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -35,6 +45,39 @@ pub struct CourseFile {
     pub module_id: Option<i64>,
 }
 
+/// What changed in a course since a given time, per context
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CourseUpdates {
+    pub instances: Vec<UpdatedInstance>,
+    warnings: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatedInstance {
+    /// Only `module` so far, `id` is then the course module id
+    pub contextlevel: String,
+    pub id: i64,
+    pub updates: Vec<InstanceUpdate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstanceUpdate {
+    /// What was updated, such as `configuration` or `fileareas`
+    pub name: String,
+    pub timeupdated: Option<i64>,
+}
+
+impl CourseUpdates {
+    /// Ids of the course modules that changed
+    pub fn modules(&self) -> Vec<i64> {
+        self.instances
+            .iter()
+            .filter(|instance| instance.contextlevel == "module" && !instance.updates.is_empty())
+            .map(|instance| instance.id)
+            .collect()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Pages {
     pub pages: Vec<Page>,
@@ -77,15 +120,27 @@ impl CourseSection {
 
 pub fn insert_course_sections(
     conn: &mut Connection,
-    sections: &mut Vec<CourseSection>,
+    sections: &mut [CourseSection],
     pages: &Pages,
     courseid: i64,
+    changeset: i64,
 ) -> Result<()> {
+    sections
+        .iter_mut()
+        .for_each(|section| section.combine(pages));
+    let stored = retrieve_course_structure(conn, courseid)?;
+    let changes = if stored.is_empty() {
+        Vec::new()
+    } else {
+        diff_sections(courseid, &stored, sections)
+    };
+
     let tx = conn.transaction()?;
+    insert_changes(&tx, changeset, changes)?;
+    mark_removed(&tx, &stored, sections, false)?;
 
     for section in sections.iter_mut() {
         section.courseid = Some(courseid);
-        section.combine(pages);
         generic_insert(&tx, section)?;
 
         for module in section.modules.iter_mut() {
@@ -106,6 +161,234 @@ pub fn insert_course_sections(
     Ok(())
 }
 
+/// Stores modules fetched on their own, as an incremental fetch does. Only
+/// `sections` holding fetched modules are given, the rest of the stored
+/// course is left as is.
+pub fn insert_course_modules(
+    conn: &mut Connection,
+    sections: &mut [CourseSection],
+    pages: &Pages,
+    courseid: i64,
+    changeset: i64,
+) -> Result<()> {
+    sections
+        .iter_mut()
+        .for_each(|section| section.combine(pages));
+    let stored = retrieve_course_structure(conn, courseid)?;
+    let changes = if stored.is_empty() {
+        Vec::new()
+    } else {
+        diff_sections(courseid, &stored, &merge_modules(stored.clone(), sections))
+    };
+
+    let tx = conn.transaction()?;
+    insert_changes(&tx, changeset, changes)?;
+    mark_removed(&tx, &stored, sections, true)?;
+
+    for section in sections.iter_mut() {
+        section.courseid = Some(courseid);
+        generic_insert(&tx, section)?;
+
+        for module in section.modules.iter_mut() {
+            module.section_id = Some(section.id);
+            generic_insert(&tx, module)?;
+
+            if let Some(contents) = &mut module.contents {
+                for content in contents.iter_mut() {
+                    content.module_id = Some(module.id);
+                    generic_insert(&tx, content)?;
+                }
+            }
+        }
+    }
+
+    tx.commit()?;
+    log::info!("Sucessfully stored course {} updates", courseid);
+    Ok(())
+}
+
+/// Applies the outline of a course, fetched without module contents, to the
+/// stored course. Sections renamed or with an edited summary are updated,
+/// modules that moved or are new are stored, and what the outline no longer
+/// holds is marked as removed. Module contents are left as stored.
+pub fn insert_course_structure(
+    conn: &mut Connection,
+    sections: &mut [CourseSection],
+    pages: &Pages,
+    courseid: i64,
+    changeset: i64,
+) -> Result<()> {
+    sections
+        .iter_mut()
+        .for_each(|section| section.combine(pages));
+    let stored = retrieve_course_structure(conn, courseid)?;
+    let mut outline = outline_with_stored_modules(&stored, sections);
+    let changes = if stored.is_empty() {
+        Vec::new()
+    } else {
+        diff_sections(courseid, &stored, &outline)
+    };
+
+    let tx = conn.transaction()?;
+    insert_changes(&tx, changeset, changes)?;
+    mark_removed(&tx, &stored, &outline, false)?;
+
+    for section in outline.iter_mut() {
+        section.courseid = Some(courseid);
+        let old_section = stored.iter().find(|old| old.id == section.id);
+        if old_section.is_none_or(|old| old.name != section.name || old.summary != section.summary)
+        {
+            generic_insert(&tx, section)?;
+        }
+
+        for module in section.modules.iter_mut() {
+            let moved_or_new = module.section_id != Some(section.id);
+            module.section_id = Some(section.id);
+            if moved_or_new {
+                generic_insert(&tx, module)?;
+            }
+        }
+    }
+
+    tx.commit()?;
+    log::info!("Sucessfully stored course {} outline", courseid);
+    Ok(())
+}
+
+// The sections of `outline` holding the stored copies of their modules, so
+// only where they are is taken from the outline. Modules not stored yet are
+// taken as they are.
+fn outline_with_stored_modules(
+    stored: &[CourseSection],
+    outline: &[CourseSection],
+) -> Vec<CourseSection> {
+    outline
+        .iter()
+        .map(|section| CourseSection {
+            modules: section
+                .modules
+                .iter()
+                .map(|module| {
+                    stored
+                        .iter()
+                        .flat_map(|stored| &stored.modules)
+                        .find(|stored| stored.id == module.id)
+                        .cloned()
+                        .unwrap_or_else(|| CourseModule {
+                            section_id: None,
+                            ..module.clone()
+                        })
+                })
+                .collect(),
+            ..section.clone()
+        })
+        .collect()
+}
+
+// Marks what is stored but missing from `sections` as removed. With
+// `partial`, `sections` only hold refetched modules, so only their files are
+// compared.
+fn mark_removed(
+    tx: &Transaction,
+    stored: &[CourseSection],
+    sections: &[CourseSection],
+    partial: bool,
+) -> Result<()> {
+    let modules: Vec<&CourseModule> = sections
+        .iter()
+        .flat_map(|section| &section.modules)
+        .collect();
+
+    for old_section in stored {
+        if !partial && !sections.iter().any(|section| section.id == old_section.id) {
+            log::info!("Section '{}' was removed", old_section.name);
+            tx.execute(
+                "UPDATE Sections SET removedat = CURRENT_TIMESTAMP WHERE sectionid = ?1",
+                params![old_section.id],
+            )?;
+        }
+
+        for old in &old_section.modules {
+            let module = modules.iter().find(|module| module.id == old.id);
+            if module.is_none() {
+                if partial {
+                    continue;
+                }
+                log::info!("Module '{}' was removed", old.name);
+                tx.execute(
+                    "UPDATE Modules SET removedat = CURRENT_TIMESTAMP WHERE moduleid = ?1",
+                    params![old.id],
+                )?;
+            }
+
+            for file in old.contents.iter().flatten() {
+                let kept = module.is_some_and(|module| {
+                    module
+                        .contents
+                        .iter()
+                        .flatten()
+                        .any(|new| new.filename == file.filename)
+                });
+                if !kept {
+                    tx.execute(
+                        "UPDATE Files SET removedat = CURRENT_TIMESTAMP WHERE filename = ?1",
+                        params![file.filename],
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// `stored` with the modules of `sections` in place of their stored copies
+fn merge_modules(mut stored: Vec<CourseSection>, sections: &[CourseSection]) -> Vec<CourseSection> {
+    for section in sections {
+        for module in &section.modules {
+            let in_place = stored
+                .iter_mut()
+                .find(|stored| stored.id == section.id)
+                .and_then(|stored| stored.modules.iter_mut().find(|m| m.id == module.id));
+            if let Some(in_place) = in_place {
+                *in_place = module.clone();
+                continue;
+            }
+
+            // Moved from another section, or new
+            stored
+                .iter_mut()
+                .for_each(|stored| stored.modules.retain(|m| m.id != module.id));
+            match stored.iter_mut().find(|stored| stored.id == section.id) {
+                Some(stored) => stored.modules.push(module.clone()),
+                None => stored.push(CourseSection {
+                    modules: vec![module.clone()],
+                    ..section.clone()
+                }),
+            }
+        }
+    }
+    stored
+}
+
+/// When the course was last fetched, as a Unix timestamp
+pub fn retrieve_last_sync(conn: &Connection, courseid: i64) -> Result<Option<i64>> {
+    let mut stmt = conn.prepare("SELECT lastsync FROM CourseSync WHERE courseid = ?1")?;
+    let mut rows = stmt.query(params![courseid])?;
+    Ok(match rows.next()? {
+        Some(row) => row.get(0)?,
+        None => None,
+    })
+}
+
+pub fn update_last_sync(conn: &Connection, courseid: i64, lastsync: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO CourseSync (courseid, lastsync) VALUES (?1, ?2)
+            ON CONFLICT(courseid) DO UPDATE SET lastsync=excluded.lastsync",
+        params![courseid, lastsync],
+    )?;
+    Ok(())
+}
+
 impl Insertable for CourseSection {
     fn insert_query() -> &'static str {
         "INSERT INTO Sections (sectionid, name, summary, courseid, lastfetched)
@@ -113,7 +396,8 @@ impl Insertable for CourseSection {
             ON CONFLICT(sectionid) DO UPDATE SET
                 name=excluded.name,
                 summary=excluded.summary,
-                lastfetched=excluded.lastfetched"
+                lastfetched=excluded.lastfetched,
+                removedat=NULL"
     }
 
     fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
@@ -134,21 +418,24 @@ impl Insertable for CourseSection {
 
 impl Insertable for CourseModule {
     fn insert_query() -> &'static str {
-        "INSERT INTO Modules (moduleid, name, instance, contextid, description, section_id, lastfetched)
-            VALUES (:moduleid, :name, :instance, :contextid, :description, :section_id, CURRENT_TIMESTAMP)
+        "INSERT INTO Modules (moduleid, name, modname, instance, contextid, description, section_id, lastfetched)
+            VALUES (:moduleid, :name, :modname, :instance, :contextid, :description, :section_id, CURRENT_TIMESTAMP)
             ON CONFLICT(moduleid) DO UPDATE SET
                 name=excluded.name,
+                modname=excluded.modname,
                 instance=excluded.instance,
                 contextid=excluded.contextid,
                 description=excluded.description,
                 section_id=excluded.section_id,
-                lastfetched=excluded.lastfetched"
+                lastfetched=excluded.lastfetched,
+                removedat=NULL"
     }
 
     fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
         log::debug!("Binding parameters for CourseModule");
         log::debug!("moduleid: {}", &self.id);
         log::debug!("name: {}", &self.name);
+        log::debug!("modname: {:?}", &self.modname);
         log::debug!("instance: {:?}", &self.instance);
         log::debug!("contextid: {:?}", &self.contextid);
         log::debug!("description: {:?}", &self.description);
@@ -157,6 +444,7 @@ impl Insertable for CourseModule {
         vec![
             (":moduleid", &self.id),
             (":name", &self.name),
+            (":modname", &self.modname),
             (":instance", &self.instance),
             (":contextid", &self.contextid),
             (":description", &self.description),
@@ -171,7 +459,8 @@ impl Insertable for CourseFile {
             VALUES (:filename, :fileurl, :timemodified, :module_id,  CURRENT_TIMESTAMP)
             ON CONFLICT(filename) DO UPDATE SET
                 timemodified=excluded.timemodified,
-                lastfetched=excluded.lastfetched"
+                lastfetched=excluded.lastfetched,
+                removedat=NULL"
     }
 
     fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
@@ -192,12 +481,12 @@ impl Insertable for CourseFile {
 
 impl Retrievable for CourseSection {
     fn select_query() -> &'static str {
-        "SELECT sectionid, name, summary, courseid
-            FROM Sections WHERE courseid = ?1"
+        "SELECT sectionid, name, summary, courseid, lastfetched
+            FROM Sections WHERE courseid = ?1 AND removedat IS NULL"
     }
     fn select_query_all() -> &'static str {
-        "SELECT sectionid, name, summary, courseid
-            FROM Sections"
+        "SELECT sectionid, name, summary, courseid, lastfetched
+            FROM Sections WHERE removedat IS NULL"
     }
 
     fn from_row(row: &Row) -> Result<Self> {
@@ -207,30 +496,33 @@ impl Retrievable for CourseSection {
             summary: row.get("summary")?,
             courseid: row.get("courseid")?,
             modules: Vec::new(), // empty vector
+            lastfetched: row.get("lastfetched")?,
         })
     }
 }
 
 impl Retrievable for CourseModule {
     fn select_query() -> &'static str {
-        "SELECT moduleid, name, instance, contextid, description, section_id
-            FROM Modules WHERE section_id = ?1"
+        "SELECT moduleid, name, modname, instance, contextid, description, section_id, lastfetched
+            FROM Modules WHERE section_id = ?1 AND removedat IS NULL"
     }
 
     fn select_query_all() -> &'static str {
-        "SELECT moduleid, name, instance, contextid, description,section_id
-            FROM Modules"
+        "SELECT moduleid, name, modname, instance, contextid, description, section_id, lastfetched
+            FROM Modules WHERE removedat IS NULL"
     }
 
     fn from_row(row: &Row) -> Result<Self> {
         Ok(CourseModule {
             id: row.get("moduleid")?,
             name: row.get("name")?,
+            modname: row.get("modname")?,
             instance: row.get("instance")?,
             contextid: row.get("contextid")?,
             description: row.get("description")?,
             contents: Some(Vec::new()), // empty vector
             section_id: row.get("section_id")?,
+            lastfetched: row.get("lastfetched")?,
         })
     }
 }
@@ -238,12 +530,15 @@ impl Retrievable for CourseModule {
 impl Retrievable for CourseFile {
     fn select_query() -> &'static str {
         "SELECT filename, fileurl, localpath, timemodified, module_id
-            FROM Files WHERE module_id = ?1"
+            FROM Files WHERE module_id = ?1 AND removedat IS NULL"
     }
 
+    // External links of url modules are handled by `downloader::save_urls`
     fn select_query_all() -> &'static str {
         "SELECT filename, fileurl, localpath, timemodified, module_id
-            FROM Files"
+            FROM Files
+            WHERE removedat IS NULL
+                AND module_id NOT IN (SELECT moduleid FROM Modules WHERE modname = 'url')"
     }
 
     fn from_row(row: &Row) -> Result<Self> {
@@ -299,3 +594,151 @@ pub fn get_all_files(conn: &mut Connection) -> Result<Vec<CourseFile>> {
     tx.commit()?;
     Ok(files)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(id: i64, modules: &[(i64, &str)]) -> CourseSection {
+        CourseSection {
+            id,
+            name: format!("Section {}", id),
+            summary: String::new(),
+            courseid: None,
+            modules: modules
+                .iter()
+                .map(|(id, name)| CourseModule {
+                    id: *id,
+                    name: name.to_string(),
+                    modname: None,
+                    instance: None,
+                    contextid: None,
+                    description: None,
+                    contents: None,
+                    section_id: None,
+                    lastfetched: None,
+                })
+                .collect(),
+            lastfetched: None,
+        }
+    }
+
+    #[test]
+    fn merges_fetched_modules_into_stored_course() {
+        let stored = vec![
+            section(1, &[(10, "Slides"), (11, "Quiz")]),
+            section(2, &[(20, "Reading")]),
+        ];
+        let fetched = [
+            section(1, &[(10, "Slides v2")]),
+            section(2, &[(11, "Quiz")]),
+            section(3, &[(30, "New")]),
+        ];
+        let merged = merge_modules(stored, &fetched);
+
+        let names: Vec<Vec<&str>> = merged
+            .iter()
+            .map(|section| section.modules.iter().map(|m| m.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            [vec!["Slides v2"], vec!["Reading", "Quiz"], vec!["New"]]
+        );
+    }
+
+    #[test]
+    fn marks_content_missing_from_a_fetch_as_removed() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        let stored = vec![
+            section(1, &[(10, "Slides"), (11, "Quiz")]),
+            section(2, &[(20, "Reading")]),
+        ];
+        let tx = conn.transaction().unwrap();
+        for section in &stored {
+            generic_insert(&tx, section).unwrap();
+            for module in &section.modules {
+                generic_insert(&tx, module).unwrap();
+            }
+        }
+
+        // A partial fetch only says something about the refetched modules
+        mark_removed(&tx, &stored, &[section(1, &[(10, "Slides")])], true).unwrap();
+        let modules: Vec<CourseModule> = generic_retrieve(&tx).unwrap();
+        assert_eq!(modules.len(), 3);
+
+        mark_removed(&tx, &stored, &[section(1, &[(10, "Slides")])], false).unwrap();
+        let sections: Vec<CourseSection> = generic_retrieve(&tx).unwrap();
+        let modules: Vec<CourseModule> = generic_retrieve(&tx).unwrap();
+        assert_eq!(sections.iter().map(|s| s.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(modules.iter().map(|m| m.id).collect::<Vec<_>>(), [10]);
+
+        // Content that comes back is restored by the upsert
+        generic_insert(&tx, &stored[1].modules[0]).unwrap();
+        let modules: Vec<CourseModule> = generic_retrieve(&tx).unwrap();
+        assert_eq!(modules.len(), 2);
+    }
+
+    #[test]
+    fn applies_an_outline_to_the_stored_course() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        let pages = Pages {
+            pages: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut stored = vec![
+            section(1, &[(10, "Slides"), (11, "Quiz")]),
+            section(2, &[(20, "Reading")]),
+        ];
+        stored[0].modules[0].contents = Some(vec![CourseFile {
+            filename: Some(String::from("slides.pdf")),
+            filepath: None,
+            fileurl: None,
+            timemodified: None,
+            module_id: None,
+        }]);
+        let changeset = crate::models::changes::begin_changeset(&conn).unwrap();
+        insert_course_sections(&mut conn, &mut stored, &pages, 1, changeset).unwrap();
+
+        // Fetched without contents: section 1 edited, module 11 and
+        // section 2 gone, module 20 moved to a new section
+        let mut outline = vec![
+            section(1, &[(10, "Slides")]),
+            section(3, &[(20, "Reading")]),
+        ];
+        outline[0].summary = String::from("Read before class");
+        let changeset = crate::models::changes::begin_changeset(&conn).unwrap();
+        insert_course_structure(&mut conn, &mut outline, &pages, 1, changeset).unwrap();
+
+        let course = retrieve_course_structure(&mut conn, 1).unwrap();
+        let ids: Vec<(i64, Vec<i64>)> = course
+            .iter()
+            .map(|section| (section.id, section.modules.iter().map(|m| m.id).collect()))
+            .collect();
+        assert_eq!(ids, [(1, vec![10]), (3, vec![20])]);
+        assert_eq!(course[0].summary, "Read before class");
+        assert_eq!(
+            course[0].modules[0].contents.as_ref().unwrap()[0].filename,
+            Some(String::from("slides.pdf"))
+        );
+
+        let changes = crate::models::changes::retrieve_changes(&mut conn, 1, None).unwrap();
+        let mut changes: Vec<(String, String)> = changes
+            .iter()
+            .map(|change| (change.kind.as_str().to_string(), change.key.clone()))
+            .collect();
+        changes.sort();
+        assert_eq!(
+            changes,
+            [
+                ("added", "3"),
+                ("modified", "1"),
+                ("modified", "20"),
+                ("removed", "11"),
+                ("removed", "2"),
+            ]
+            .map(|(kind, key)| (kind.to_string(), key.to_string()))
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// models/embedded.rs
+//
+use crate::{
+    db::{generic_insert, retrieve_param, Insertable, Retrievable},
+    models::course::CourseSection,
+    parser::collect_pluginfile_urls,
+};
+use eyre::Result;
+use rusqlite::{params, Connection, Row, ToSql};
+use serde::{Deserialize, Serialize};
+
+/// A file uploaded to Moodle and referenced from a section summary or module description
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmbeddedFile {
+    pub url: String,
+    pub localpath: Option<String>,
+    pub courseid: i64,
+}
+
+pub fn insert_embedded_files(
+    conn: &mut Connection,
+    sections: &[CourseSection],
+    courseid: i64,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    for section in sections {
+        let mut urls = collect_pluginfile_urls(&section.summary);
+        for module in &section.modules {
+            if let Some(description) = &module.description {
+                urls.append(&mut collect_pluginfile_urls(description));
+            }
+        }
+
+        for url in urls {
+            let file = EmbeddedFile {
+                url,
+                localpath: None,
+                courseid,
+            };
+            generic_insert(&tx, &file)?;
+        }
+    }
+
+    tx.commit()?;
+    log::info!("Successfully stored course {} embedded files", courseid);
+    Ok(())
+}
+
+impl Insertable for EmbeddedFile {
+    fn insert_query() -> &'static str {
+        "INSERT INTO EmbeddedFiles (url, courseid, lastfetched)
+            VALUES (:url, :courseid, CURRENT_TIMESTAMP)
+            ON CONFLICT(url) DO UPDATE SET
+                courseid=excluded.courseid,
+                lastfetched=excluded.lastfetched"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        log::debug!("Binding parameters for EmbeddedFile");
+        log::debug!("url: {}", &self.url);
+        log::debug!("courseid: {}", &self.courseid);
+
+        vec![(":url", &self.url), (":courseid", &self.courseid)]
+    }
+}
+
+impl Retrievable for EmbeddedFile {
+    fn select_query() -> &'static str {
+        "SELECT url, localpath, courseid
+            FROM EmbeddedFiles WHERE courseid = ?1"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT url, localpath, courseid
+            FROM EmbeddedFiles"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(EmbeddedFile {
+            url: row.get("url")?,
+            localpath: row.get("localpath")?,
+            courseid: row.get("courseid")?,
+        })
+    }
+}
+
+pub fn retrieve_course_embedded_files(
+    conn: &mut Connection,
+    courseid: i64,
+) -> Result<Vec<EmbeddedFile>> {
+    log::debug!("Retrieving course {} embedded files", courseid);
+    let tx = conn.transaction()?;
+    let files: Vec<EmbeddedFile> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(files)
+}
+
+pub fn update_embedded_path_in_db(conn: &Connection, url: &str, localpath: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE EmbeddedFiles SET localpath = ? WHERE url = ?",
+        params![localpath, url],
+    )?;
+    Ok(())
+}
@@ -1,9 +1,13 @@
 // models/grades.rs
 //
-use crate::db::{generic_insert, retrieve_param, Insertable, Retrievable};
+use crate::{
+    db::{generic_insert, retrieve_param, Insertable, Retrievable},
+    models::changes::{diff_grades, insert_changes},
+};
 use eyre::Result;
 use rusqlite::{params, Connection, Row, ToSql};
-use {serde::Deserialize, serde::Serialize};
+use std::collections::HashMap;
+use {serde::Deserialize, serde::Deserializer, serde::Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CourseGrades {
@@ -16,24 +20,234 @@ pub struct CourseGrades {
 pub struct GradeItem {
     pub id: i64,
     pub itemname: Option<String>,
+    /// `course`, `category`, `mod` or `manual`. Course and category items hold
+    /// the totals Moodle computed, their instance is the category id.
+    #[serde(default)]
+    pub itemtype: Option<String>,
     pub itemmodule: Option<String>,
     pub iteminstance: i64,
     pub itemnumber: Option<i64>,
     pub idnumber: Option<String>,
     pub categoryid: Option<i64>,
     pub cmid: Option<i64>,
-    pub graderaw: Option<i64>,
+    pub graderaw: Option<f64>,
     pub gradedatesubmitted: Option<i64>,
     pub gradedategraded: Option<i64>,
-    pub grademin: i64,
-    pub grademax: i64,
+    pub grademin: f64,
+    pub grademax: f64,
     pub feedback: Option<String>,
     pub courseid: Option<i64>,
+    /// Weight within its category as a fraction, as Moodle normalizes it
+    #[serde(default)]
+    pub weightraw: Option<f64>,
+    /// The grade as the course's grade display type shows it, `-` if none
+    #[serde(default)]
+    pub gradeformatted: Option<String>,
+    #[serde(default)]
+    pub percentageformatted: Option<String>,
+    #[serde(default)]
+    pub lettergradeformatted: Option<String>,
 }
 
-pub fn insert_grades(conn: &mut Connection, mut course_grades: Vec<CourseGrades>) -> Result<()> {
-    let tx = conn.transaction()?;
+impl GradeItem {
+    pub fn is_total(&self) -> bool {
+        matches!(self.itemtype.as_deref(), Some("course" | "category"))
+    }
+
+    /// Name to show, totals have none of their own
+    pub fn display_name(&self) -> Option<&str> {
+        match (self.itemname.as_deref(), self.itemtype.as_deref()) {
+            (Some(name), _) if !name.trim().is_empty() => Some(name),
+            (_, Some("course")) => Some("Course total"),
+            (_, Some("category")) => Some("Category total"),
+            _ => None,
+        }
+    }
+
+    /// Where `grade` falls in the item's range, in percent
+    pub fn percentage_of(&self, grade: f64) -> Option<f64> {
+        let range = self.grademax - self.grademin;
+        (range > 0.0).then(|| (grade - self.grademin) / range * 100.0)
+    }
+
+    pub fn percentage(&self) -> Option<f64> {
+        self.percentage_of(self.graderaw?)
+    }
+
+    /// The grade, or N/A when there is none
+    pub fn grade(&self) -> String {
+        self.graderaw
+            .map_or_else(|| String::from("N/A"), format_grade)
+    }
+
+    pub fn range(&self) -> String {
+        format!(
+            "{} - {}",
+            format_grade(self.grademin),
+            format_grade(self.grademax)
+        )
+    }
+
+    /// Letter grade when the grade report shows letters
+    pub fn letter(&self) -> Option<&str> {
+        self.lettergradeformatted
+            .as_deref()
+            .map(str::trim)
+            .filter(|letter| !letter.is_empty() && *letter != "-")
+    }
+}
+
+/// `grade` with at most two decimals and without trailing zeros
+pub fn format_grade(grade: f64) -> String {
+    let formatted = format!("{:.2}", grade);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    match formatted {
+        "-0" => String::from("0"),
+        formatted => formatted.to_string(),
+    }
+}
+
+/// Course total in percent over the graded items, with `what_if` grades by
+/// item id standing in for those not graded yet. Items count with their
+/// weight times that of their categories, or by their range when Moodle
+/// doesn't report weights, as in its natural aggregation.
+pub fn projected_percentage(grades: &[GradeItem], what_if: &HashMap<i64, f64>) -> Option<f64> {
+    let items: Vec<&GradeItem> = grades.iter().filter(|item| !item.is_total()).collect();
+    let weighted = items.iter().all(|item| item.weightraw.is_some());
+    let root = grades
+        .iter()
+        .find(|item| item.itemtype.as_deref() == Some("course"))
+        .map(|item| item.iteminstance);
+
+    let (mut total, mut weights) = (0.0, 0.0);
+    for item in items {
+        let grade = item.graderaw.or_else(|| what_if.get(&item.id).copied());
+        let Some(percentage) = grade.and_then(|grade| item.percentage_of(grade)) else {
+            continue;
+        };
+
+        let weight = if weighted {
+            item.weightraw.unwrap_or_default() * category_weight(grades, item.categoryid, root)
+        } else {
+            item.grademax - item.grademin
+        };
+        total += weight * percentage;
+        weights += weight;
+    }
+    (weights > 0.0).then(|| total / weights)
+}
+
+// Share of the course total the category makes up
+fn category_weight(grades: &[GradeItem], category: Option<i64>, root: Option<i64>) -> f64 {
+    let mut weight = 1.0;
+    let mut category = category;
+    // Bounded in case categories refer to each other
+    for _ in 0..grades.len() {
+        let Some(id) = category.filter(|id| Some(*id) != root) else {
+            break;
+        };
+        let Some(item) = grades
+            .iter()
+            .find(|item| item.itemtype.as_deref() == Some("category") && item.iteminstance == id)
+        else {
+            break;
+        };
+        weight *= item.weightraw.unwrap_or(1.0);
+        category = item.categoryid;
+    }
+    weight
+}
+
+/// Course total as the grade overview report shows it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CourseTotal {
+    pub courseid: i64,
+    /// As the course's grade display type shows it
+    pub grade: Option<String>,
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub rawgrade: Option<f64>,
+    /// Only reported when the course shows ranks
+    #[serde(default)]
+    pub rank: Option<i64>,
+}
+
+// The overview report sends raw grades as strings
+fn number_or_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<f64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(f64),
+        Text(String),
+    }
+
+    Ok(match Option::<Raw>::deserialize(deserializer)? {
+        Some(Raw::Number(number)) => Some(number),
+        Some(Raw::Text(text)) => text.trim().parse().ok(),
+        None => None,
+    })
+}
 
+/// Values of a grade item as seen by a fetch, recorded whenever they change
+#[derive(Debug, Serialize)]
+pub struct GradeHistory {
+    pub gradeid: i64,
+    pub courseid: Option<i64>,
+    pub itemname: Option<String>,
+    pub graderaw: Option<f64>,
+    pub feedback: Option<String>,
+    pub gradedategraded: Option<i64>,
+    /// Only set when read from the database
+    pub observedat: Option<String>,
+}
+
+impl GradeHistory {
+    fn observe(grade: &GradeItem, courseid: i64) -> Self {
+        GradeHistory {
+            gradeid: grade.id,
+            courseid: Some(courseid),
+            itemname: grade.itemname.clone(),
+            graderaw: grade.graderaw,
+            feedback: grade.feedback.clone(),
+            gradedategraded: grade.gradedategraded,
+            observedat: None,
+        }
+    }
+}
+
+pub fn insert_grades(
+    conn: &mut Connection,
+    mut course_grades: Vec<CourseGrades>,
+    changeset: i64,
+) -> Result<()> {
+    let mut changes = Vec::new();
+    let mut history = Vec::new();
+    for grades in &course_grades {
+        let stored = retrieve_course_grades(conn, grades.courseid)?;
+        if !stored.is_empty() {
+            changes.append(&mut diff_grades(
+                grades.courseid,
+                &stored,
+                &grades.gradeitems,
+            ));
+        }
+
+        for grade in &grades.gradeitems {
+            let unchanged = stored.iter().any(|old| {
+                old.id == grade.id
+                    && old.graderaw == grade.graderaw
+                    && old.feedback == grade.feedback
+                    && old.gradedategraded == grade.gradedategraded
+            });
+            if !unchanged {
+                history.push(GradeHistory::observe(grade, grades.courseid));
+            }
+        }
+    }
+
+    let tx = conn.transaction()?;
+    insert_changes(&tx, changeset, changes)?;
     for grades in course_grades.iter_mut() {
         for grade in grades.gradeitems.iter_mut() {
             grade.courseid = Some(grades.courseid);
@@ -41,6 +255,11 @@ pub fn insert_grades(conn: &mut Connection, mut course_grades: Vec<CourseGrades>
         }
     }
 
+    // After the grades, which the history refers to
+    for observed in &history {
+        generic_insert(&tx, observed)?;
+    }
+
     tx.commit()?;
     log::info!("Sucessfully stored course grades");
     Ok(())
@@ -49,16 +268,18 @@ pub fn insert_grades(conn: &mut Connection, mut course_grades: Vec<CourseGrades>
 impl Insertable for GradeItem {
     fn insert_query() -> &'static str {
         "INSERT INTO Grades (
-        gradeid, itemname, itemmodule, iteminstance, itemnumber, idnumber, categoryid,
+        gradeid, itemname, itemtype, itemmodule, iteminstance, itemnumber, idnumber, categoryid,
         cmid, graderaw, gradedatesubmitted, gradedategraded, grademin, grademax, feedback,
-        courseid, lastfetched)
+        weightraw, gradeformatted, percentageformatted, lettergradeformatted, courseid, lastfetched)
             VALUES (
-                :gradeid, :itemname, :itemmodule, :iteminstance, :itemnumber, :idnumber, :categoryid,
+                :gradeid, :itemname, :itemtype, :itemmodule, :iteminstance, :itemnumber, :idnumber, :categoryid,
                 :cmid, :graderaw, :gradedatesubmitted, :gradedategraded, :grademin, :grademax, :feedback,
-                :courseid, CURRENT_TIMESTAMP
+                :weightraw, :gradeformatted, :percentageformatted, :lettergradeformatted, :courseid,
+                CURRENT_TIMESTAMP
             )
             ON CONFLICT(gradeid) DO UPDATE SET
                 itemname=excluded.itemname,
+                itemtype=excluded.itemtype,
                 itemmodule=excluded.itemmodule,
                 iteminstance=excluded.iteminstance,
                 itemnumber=excluded.itemnumber,
@@ -71,6 +292,10 @@ impl Insertable for GradeItem {
                 grademin=excluded.grademin,
                 grademax=excluded.grademax,
                 feedback=excluded.feedback,
+                weightraw=excluded.weightraw,
+                gradeformatted=excluded.gradeformatted,
+                percentageformatted=excluded.percentageformatted,
+                lettergradeformatted=excluded.lettergradeformatted,
                 courseid=excluded.courseid,
                 lastfetched=excluded.lastfetched"
     }
@@ -79,6 +304,7 @@ impl Insertable for GradeItem {
         log::debug!("Binding parameters for GradeItem");
         log::debug!("gradeid: {}", &self.id);
         log::debug!("itemname: {:?}", &self.itemname);
+        log::debug!("itemtype: {:?}", &self.itemtype);
         log::debug!("itemmodule: {:?}", &self.itemmodule);
         log::debug!("iteminstance: {:?}", &self.iteminstance);
         log::debug!("itemnumber: {:?}", &self.itemnumber);
@@ -91,11 +317,16 @@ impl Insertable for GradeItem {
         log::debug!("grademin: {:?}", &self.grademin);
         log::debug!("grademax: {:?}", &self.grademax);
         log::debug!("feedback: {:?}", &self.feedback);
+        log::debug!("weightraw: {:?}", &self.weightraw);
+        log::debug!("gradeformatted: {:?}", &self.gradeformatted);
+        log::debug!("percentageformatted: {:?}", &self.percentageformatted);
+        log::debug!("lettergradeformatted: {:?}", &self.lettergradeformatted);
         log::debug!("courseid: {:?}", &self.courseid);
 
         vec![
             (":gradeid", &self.id),
             (":itemname", &self.itemname),
+            (":itemtype", &self.itemtype),
             (":itemmodule", &self.itemmodule),
             (":iteminstance", &self.iteminstance),
             (":itemnumber", &self.itemnumber),
@@ -108,20 +339,130 @@ impl Insertable for GradeItem {
             (":grademin", &self.grademin),
             (":grademax", &self.grademax),
             (":feedback", &self.feedback),
+            (":weightraw", &self.weightraw),
+            (":gradeformatted", &self.gradeformatted),
+            (":percentageformatted", &self.percentageformatted),
+            (":lettergradeformatted", &self.lettergradeformatted),
+            (":courseid", &self.courseid),
+        ]
+    }
+}
+
+pub fn insert_course_totals(conn: &mut Connection, totals: &[CourseTotal]) -> Result<()> {
+    let tx = conn.transaction()?;
+    for total in totals {
+        generic_insert(&tx, total)?;
+    }
+    tx.commit()?;
+    log::info!("Stored {} course totals", totals.len());
+    Ok(())
+}
+
+impl Insertable for CourseTotal {
+    fn insert_query() -> &'static str {
+        "INSERT INTO CourseTotals (courseid, grade, rawgrade, rank, lastfetched)
+            VALUES (:courseid, :grade, :rawgrade, :rank, CURRENT_TIMESTAMP)
+            ON CONFLICT(courseid) DO UPDATE SET
+                grade=excluded.grade,
+                rawgrade=excluded.rawgrade,
+                rank=excluded.rank,
+                lastfetched=excluded.lastfetched"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![
+            (":courseid", &self.courseid),
+            (":grade", &self.grade),
+            (":rawgrade", &self.rawgrade),
+            (":rank", &self.rank),
+        ]
+    }
+}
+
+impl Retrievable for CourseTotal {
+    fn select_query() -> &'static str {
+        "SELECT courseid, grade, rawgrade, rank FROM CourseTotals WHERE courseid = ?1"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT courseid, grade, rawgrade, rank FROM CourseTotals"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(CourseTotal {
+            courseid: row.get("courseid")?,
+            grade: row.get("grade")?,
+            rawgrade: row.get("rawgrade")?,
+            rank: row.get("rank")?,
+        })
+    }
+}
+
+/// The course total of the last fetch, if the overview report had one
+pub fn retrieve_course_total(conn: &mut Connection, courseid: i64) -> Result<Option<CourseTotal>> {
+    let tx = conn.transaction()?;
+    let totals: Vec<CourseTotal> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(totals.into_iter().next())
+}
+
+impl Insertable for GradeHistory {
+    fn insert_query() -> &'static str {
+        "INSERT INTO GradeHistory (
+        gradeid, courseid, itemname, graderaw, feedback, gradedategraded, observedat)
+            VALUES (
+                :gradeid, :courseid, :itemname, :graderaw, :feedback, :gradedategraded,
+                CURRENT_TIMESTAMP
+            )"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        vec![
+            (":gradeid", &self.gradeid),
             (":courseid", &self.courseid),
+            (":itemname", &self.itemname),
+            (":graderaw", &self.graderaw),
+            (":feedback", &self.feedback),
+            (":gradedategraded", &self.gradedategraded),
         ]
     }
 }
 
+impl Retrievable for GradeHistory {
+    fn select_query() -> &'static str {
+        "SELECT gradeid, courseid, itemname, graderaw, feedback, gradedategraded, observedat
+            FROM GradeHistory WHERE courseid = ?1 ORDER BY id"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT gradeid, courseid, itemname, graderaw, feedback, gradedategraded, observedat
+            FROM GradeHistory ORDER BY id"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(GradeHistory {
+            gradeid: row.get("gradeid")?,
+            courseid: row.get("courseid")?,
+            itemname: row.get("itemname")?,
+            graderaw: row.get("graderaw")?,
+            feedback: row.get("feedback")?,
+            gradedategraded: row.get("gradedategraded")?,
+            observedat: row.get("observedat")?,
+        })
+    }
+}
+
 impl Retrievable for GradeItem {
     fn select_query() -> &'static str {
-        "SELECT gradeid, itemname, itemmodule, iteminstance, itemnumber, idnumber, categoryid, cmid,
-            graderaw, gradedatesubmitted, gradedategraded, grademin, grademax, feedback, courseid
+        "SELECT gradeid, itemname, itemtype, itemmodule, iteminstance, itemnumber, idnumber,
+            categoryid, cmid, graderaw, gradedatesubmitted, gradedategraded, grademin, grademax,
+            feedback, weightraw, gradeformatted, percentageformatted, lettergradeformatted, courseid
             FROM Grades WHERE courseid = ?1"
     }
     fn select_query_all() -> &'static str {
-        "SELECT gradeid, itemname, itemmodule, iteminstance, itemnumber, idnumber, categoryid, cmid,
-            graderaw, gradedatesubmitted, gradedategraded, grademin, grademax, feedback, courseid
+        "SELECT gradeid, itemname, itemtype, itemmodule, iteminstance, itemnumber, idnumber,
+            categoryid, cmid, graderaw, gradedatesubmitted, gradedategraded, grademin, grademax,
+            feedback, weightraw, gradeformatted, percentageformatted, lettergradeformatted, courseid
             FROM Grades"
     }
 
@@ -129,6 +470,7 @@ impl Retrievable for GradeItem {
         Ok(GradeItem {
             id: row.get("gradeid")?,
             itemname: row.get("itemname")?,
+            itemtype: row.get("itemtype")?,
             itemmodule: row.get("itemmodule")?,
             iteminstance: row.get("iteminstance")?,
             itemnumber: row.get("itemnumber")?,
@@ -141,6 +483,10 @@ impl Retrievable for GradeItem {
             grademin: row.get("grademin")?,
             grademax: row.get("grademax")?,
             feedback: row.get("feedback")?,
+            weightraw: row.get("weightraw")?,
+            gradeformatted: row.get("gradeformatted")?,
+            percentageformatted: row.get("percentageformatted")?,
+            lettergradeformatted: row.get("lettergradeformatted")?,
             courseid: row.get("courseid")?,
         })
     }
@@ -165,3 +511,115 @@ pub fn retrieve_course_grades(conn: &mut Connection, courseid: i64) -> Result<Ve
     log::info!("Successfully retrieved course {} grades", courseid);
     Ok(grades)
 }
+
+/// Every recorded value of the course's grade items, oldest first
+pub fn retrieve_grade_history(conn: &mut Connection, courseid: i64) -> Result<Vec<GradeHistory>> {
+    let tx = conn.transaction()?;
+    let history: Vec<GradeHistory> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, itemtype: &str, graderaw: Option<f64>, grademax: f64) -> GradeItem {
+        GradeItem {
+            id,
+            itemname: None,
+            itemtype: Some(itemtype.to_string()),
+            itemmodule: None,
+            iteminstance: id,
+            itemnumber: None,
+            idnumber: None,
+            categoryid: None,
+            cmid: None,
+            graderaw,
+            gradedatesubmitted: None,
+            gradedategraded: None,
+            grademin: 0.0,
+            grademax,
+            feedback: None,
+            courseid: None,
+            weightraw: None,
+            gradeformatted: None,
+            percentageformatted: None,
+            lettergradeformatted: None,
+        }
+    }
+
+    #[test]
+    fn projects_weighted_and_natural_totals() {
+        // Natural aggregation weighs items by their range
+        let grades = vec![
+            item(1, "mod", Some(8.0), 10.0),
+            item(2, "mod", None, 30.0),
+            item(3, "course", Some(8.0), 40.0),
+        ];
+        assert_eq!(projected_percentage(&grades, &HashMap::new()), Some(80.0));
+        let what_if = HashMap::from([(2, 15.0)]);
+        assert_eq!(projected_percentage(&grades, &what_if), Some(57.5));
+
+        // Weights of nested categories multiply
+        let mut weighted = grades;
+        weighted[0].weightraw = Some(0.5);
+        weighted[1].weightraw = Some(0.5);
+        weighted[1].categoryid = Some(4);
+        let mut category = item(4, "category", None, 100.0);
+        category.weightraw = Some(0.5);
+        weighted.push(category);
+        let projected = projected_percentage(&weighted, &what_if).unwrap();
+        assert!((projected - (0.5 * 80.0 + 0.25 * 50.0) / 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_fractional_grades() {
+        let json = r#"{
+            "id": 1, "itemname": "Quiz", "itemmodule": "quiz", "iteminstance": 2,
+            "itemnumber": 0, "idnumber": "", "categoryid": 3, "cmid": 4,
+            "graderaw": 87.5, "gradedatesubmitted": null, "gradedategraded": null,
+            "grademin": 0, "grademax": 100.5, "feedback": "", "courseid": null,
+            "gradeformatted": "87.50", "percentageformatted": "87.06 %",
+            "lettergradeformatted": "B+"
+        }"#;
+        let grade: GradeItem = serde_json::from_str(json).unwrap();
+        assert_eq!(grade.graderaw, Some(87.5));
+        assert_eq!(grade.grade(), "87.5");
+        assert_eq!(grade.range(), "0 - 100.5");
+        assert_eq!(grade.letter(), Some("B+"));
+        assert_eq!(format_grade(86.666_67), "86.67");
+        assert_eq!(format_grade(-0.001), "0");
+
+        let total: CourseTotal =
+            serde_json::from_str(r#"{"courseid": 1, "grade": "87.50", "rawgrade": "87.50000"}"#)
+                .unwrap();
+        assert_eq!((total.rawgrade, total.rank), (Some(87.5), None));
+    }
+
+    #[test]
+    fn records_history_only_when_a_grade_changes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        let mut fetch = |graderaw: Option<f64>, feedback: Option<&str>| {
+            let mut grade = item(1, "mod", graderaw, 10.0);
+            grade.feedback = feedback.map(str::to_string);
+            let changeset = crate::models::changes::begin_changeset(&conn).unwrap();
+            let grades = CourseGrades {
+                courseid: 2,
+                userid: 3,
+                gradeitems: vec![grade],
+            };
+            insert_grades(&mut conn, vec![grades], changeset).unwrap();
+            retrieve_grade_history(&mut conn, 2).unwrap().len()
+        };
+
+        assert_eq!(fetch(None, None), 1);
+        assert_eq!(fetch(None, None), 1);
+        assert_eq!(fetch(Some(7.0), None), 2);
+        assert_eq!(fetch(Some(7.0), None), 2);
+        assert_eq!(fetch(Some(8.0), None), 3);
+        assert_eq!(fetch(Some(8.0), Some("Well done")), 4);
+        assert_eq!(fetch(Some(8.0), Some("Well done")), 4);
+    }
+}
@@ -1,7 +1,11 @@
 pub mod assignments;
+pub mod changes;
+pub mod completion;
 pub mod configs;
 pub mod course;
 pub mod courses;
+pub mod embedded;
 pub mod grades;
 pub mod scorm;
+pub mod urls;
 pub mod user;
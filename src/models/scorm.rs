@@ -28,29 +28,19 @@ pub struct Scorm {
     pub maxattempt: i64,
 }
 
-fn remove_emojis(filename: String) -> String {
-    filename.chars().filter(|&c| c.is_ascii()).collect()
-}
-
 pub fn insert_scorms(conn: &mut Connection, mut scorms: Scorms) -> Result<()> {
     let tx = conn.transaction()?;
 
     for scorm in scorms.scorms.iter_mut() {
-        match &scorm.packageurl {
-            url => {
-                let file_name = remove_emojis(scorm.name.clone());
-                let file_url = &format!("{}?forcedownload=1", url);
-                let file = CourseFile {
-                    filename: Some(file_name),
-                    filepath: None,
-                    fileurl: Some(file_url.to_string()),
-                    timemodified: None,
-                    module_id: Some(scorm.coursemodule.clone()),
-                };
-                generic_insert(&tx, &file)?;
-            }
-            // _ => (),
-        }
+        let file_url = format!("{}?forcedownload=1", scorm.packageurl);
+        let file = CourseFile {
+            filename: Some(scorm.name.clone()),
+            filepath: None,
+            fileurl: Some(file_url),
+            timemodified: None,
+            module_id: Some(scorm.coursemodule),
+        };
+        generic_insert(&tx, &file)?;
         generic_insert(&tx, scorm)?;
     }
 
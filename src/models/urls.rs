@@ -0,0 +1,116 @@
+// models/urls.rs
+//
+use crate::db::{generic_insert, retrieve_param, Insertable, Retrievable};
+use eyre::Result;
+use rusqlite::{params, Connection, Row, ToSql};
+use {serde::Deserialize, serde::Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Urls {
+    pub urls: Vec<UrlModule>,
+    warnings: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UrlModule {
+    pub id: i64,
+    pub coursemodule: i64,
+    pub course: i64,
+    pub name: String,
+    pub intro: Option<String>,
+    pub externalurl: String,
+    pub timemodified: Option<i64>,
+    pub localpath: Option<String>,
+}
+
+pub fn insert_urls(conn: &mut Connection, urls: Urls) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    for url in urls.urls.iter() {
+        generic_insert(&tx, url)?;
+    }
+
+    tx.commit()?;
+    log::info!("Successfully stored course urls");
+    Ok(())
+}
+
+impl Insertable for UrlModule {
+    fn insert_query() -> &'static str {
+        "INSERT INTO Urls (
+        urlid, coursemodule, courseid, name, intro, externalurl, timemodified, lastfetched)
+        VALUES (:urlid, :coursemodule, :courseid, :name, :intro, :externalurl, :timemodified,
+            CURRENT_TIMESTAMP)
+        ON CONFLICT(urlid) DO UPDATE SET
+            coursemodule=excluded.coursemodule,
+            courseid=excluded.courseid,
+            name=excluded.name,
+            intro=excluded.intro,
+            externalurl=excluded.externalurl,
+            timemodified=excluded.timemodified,
+            lastfetched=excluded.lastfetched"
+    }
+
+    fn bind_parameters(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        log::debug!("Binding parameters for UrlModule");
+        log::debug!("urlid: {}", &self.id);
+        log::debug!("coursemodule: {:?}", &self.coursemodule);
+        log::debug!("courseid: {:?}", &self.course);
+        log::debug!("name: {:?}", &self.name);
+        log::debug!("externalurl: {:?}", &self.externalurl);
+
+        vec![
+            (":urlid", &self.id),
+            (":coursemodule", &self.coursemodule),
+            (":courseid", &self.course),
+            (":name", &self.name),
+            (":intro", &self.intro),
+            (":externalurl", &self.externalurl),
+            (":timemodified", &self.timemodified),
+        ]
+    }
+}
+
+impl Retrievable for UrlModule {
+    fn select_query() -> &'static str {
+        "SELECT urlid, coursemodule, courseid, name, intro, externalurl, timemodified, localpath
+            FROM Urls WHERE courseid = ?1
+                AND coursemodule NOT IN (SELECT moduleid FROM Modules WHERE removedat IS NOT NULL)"
+    }
+
+    fn select_query_all() -> &'static str {
+        "SELECT urlid, coursemodule, courseid, name, intro, externalurl, timemodified, localpath
+            FROM Urls
+            WHERE coursemodule NOT IN (SELECT moduleid FROM Modules WHERE removedat IS NOT NULL)"
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(UrlModule {
+            id: row.get("urlid")?,
+            coursemodule: row.get("coursemodule")?,
+            course: row.get("courseid")?,
+            name: row.get("name")?,
+            intro: row.get("intro")?,
+            externalurl: row.get("externalurl")?,
+            timemodified: row.get("timemodified")?,
+            localpath: row.get("localpath")?,
+        })
+    }
+}
+
+pub fn retrieve_course_urls(conn: &mut Connection, courseid: i64) -> Result<Vec<UrlModule>> {
+    log::debug!("Retrieving course {} urls", courseid);
+    let tx = conn.transaction()?;
+    let urls: Vec<UrlModule> = retrieve_param(&tx, params![courseid])?;
+    tx.commit()?;
+    log::info!("Successfully retrieved course {} urls", courseid);
+    Ok(urls)
+}
+
+pub fn update_url_path_in_db(conn: &Connection, urlid: i64, localpath: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE Urls SET localpath = ? WHERE urlid = ?",
+        params![localpath, urlid],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,434 @@
+// notifier.rs
+//
+// Tells the user what a fetch changed and which assignments come due soon,
+// through the command, webhooks and email set up under `[notify]`.
+use crate::{
+    db::connect_db,
+    models::{
+        assignments::retrieve_course_assignments,
+        changes::{retrieve_changes, Change, ChangeKind},
+        completion::retrieve_activity_completion,
+        configs::{Configs, EmailConfig, EmailTls, NotifyConfig, WebhookConfig, WebhookFormat},
+    },
+    utils::{format_date, parse_duration},
+};
+use chrono::Utc;
+use eyre::{eyre, Result, WrapErr};
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+// Discord refuses longer messages
+const DISCORD_LIMIT: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    Changes,
+    Reminder,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub courseid: i64,
+    pub course: String,
+    pub title: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+    /// Due date of the assignment a reminder is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duedate: Option<i64>,
+    /// Course module and leads of a reminder, recorded once it is delivered
+    #[serde(skip)]
+    reminder: Option<(i64, Vec<i64>)>,
+}
+
+/// Sends a notification for each course `changeset` changed and for each
+/// assignment coming due. A backend that fails is logged and the others
+/// still get the notification.
+pub async fn notify(config: &Configs, changeset: i64) -> Result<()> {
+    let settings = &config.notify;
+    if !settings.is_enabled() {
+        return Ok(());
+    }
+
+    let mut conn = connect_db()?;
+    let changes = retrieve_changes(&mut conn, 1, None)?
+        .into_iter()
+        .filter(|change| change.changeset == changeset)
+        .collect();
+    let mut notifications = change_notifications(config, changes);
+    notifications.append(&mut due_reminders(
+        &mut conn,
+        config,
+        Utc::now().timestamp(),
+    )?);
+
+    let client = reqwest::Client::new();
+    for notification in &notifications {
+        log::info!("Sending notification '{}'", notification.title);
+        let delivered = send(&client, settings, notification).await;
+        // Undelivered reminders are tried again after the next fetch
+        if let (true, Some((cmid, leads)), Some(duedate)) =
+            (delivered, &notification.reminder, notification.duedate)
+        {
+            mark_reminders_sent(&conn, *cmid, duedate, leads)?;
+        }
+    }
+    Ok(())
+}
+
+// Whether any backend delivered the notification
+async fn send(
+    client: &reqwest::Client,
+    settings: &NotifyConfig,
+    notification: &Notification,
+) -> bool {
+    let mut delivered = false;
+    if !settings.command.is_empty() {
+        match run_command(&settings.command, notification).await {
+            Ok(()) => delivered = true,
+            Err(e) => log::error!("Notification command failed: {:?}", e),
+        }
+    }
+    for webhook in &settings.webhooks {
+        match post_webhook(client, webhook, notification).await {
+            Ok(()) => delivered = true,
+            Err(e) => log::error!("Notification webhook failed: {:?}", e),
+        }
+    }
+    if let Some(email) = &settings.email {
+        match send_email(email, notification).await {
+            Ok(()) => delivered = true,
+            Err(e) => log::error!("Notification email failed: {:?}", e),
+        }
+    }
+    delivered
+}
+
+/// One notification per course, for the kinds of changes `[notify]` asks for
+fn change_notifications(config: &Configs, changes: Vec<Change>) -> Vec<Notification> {
+    let mut notifications: Vec<Notification> = Vec::new();
+
+    for change in changes {
+        if !config.notify.changes.contains(&change.entity) {
+            continue;
+        }
+        let line = match &change.detail {
+            Some(detail) => format!(
+                "{} {} {}: {}",
+                change.kind.as_str(),
+                change.entity.as_str(),
+                change.name,
+                detail
+            ),
+            None => format!(
+                "{} {} {}",
+                change.kind.as_str(),
+                change.entity.as_str(),
+                change.name
+            ),
+        };
+
+        match notifications
+            .iter_mut()
+            .find(|notification| notification.courseid == change.courseid)
+        {
+            Some(notification) => {
+                notification.message.push('\n');
+                notification.message.push_str(&line);
+                notification.changes.push(change);
+            }
+            None => notifications.push(Notification {
+                kind: NotificationKind::Changes,
+                courseid: change.courseid,
+                course: course_name(config, change.courseid),
+                title: String::new(),
+                message: line,
+                changes: vec![change],
+                duedate: None,
+                reminder: None,
+            }),
+        }
+    }
+
+    for notification in notifications.iter_mut() {
+        let count = |kind: ChangeKind| {
+            notification
+                .changes
+                .iter()
+                .filter(|change| change.kind == kind)
+                .count()
+        };
+        let counts: Vec<String> = [ChangeKind::Added, ChangeKind::Removed, ChangeKind::Modified]
+            .into_iter()
+            .filter(|kind| count(*kind) > 0)
+            .map(|kind| format!("{} {}", count(kind), kind.as_str()))
+            .collect();
+        notification.title = format!("{}: {}", notification.course, counts.join(", "));
+    }
+    notifications
+}
+
+/// Reminders for assignments due within one of the `reminders` leads of
+/// `now`. Each is sent once per lead and due date, so an extended deadline is
+/// reminded of again, see `mark_reminders_sent`. Assignments marked complete
+/// are left out.
+fn due_reminders(conn: &mut Connection, config: &Configs, now: i64) -> Result<Vec<Notification>> {
+    let mut leads = Vec::new();
+    for reminder in &config.notify.reminders {
+        match parse_duration(reminder) {
+            Ok(lead) => leads.push(lead.as_secs() as i64),
+            Err(e) => log::warn!("Ignoring the reminder '{}': {}", reminder, e),
+        }
+    }
+
+    let mut notifications = Vec::new();
+    if leads.is_empty() {
+        return Ok(notifications);
+    }
+
+    for course in &config.courses {
+        let completion = retrieve_activity_completion(conn, course.id)?;
+        for assignment in retrieve_course_assignments(conn, course.id)? {
+            let due = due_leads(assignment.duedate, now, &leads);
+            let completed = completion.iter().any(|status| {
+                status.cmid == assignment.cmid && status.is_tracked() && !status.is_open()
+            });
+            if due.is_empty() || completed {
+                continue;
+            }
+            let unsent = unsent_leads(conn, assignment.cmid, assignment.duedate, &due)?;
+            if unsent.is_empty() {
+                continue;
+            }
+
+            let course_name = course_name(config, course.id);
+            notifications.push(Notification {
+                kind: NotificationKind::Reminder,
+                courseid: course.id,
+                title: format!(
+                    "{}: {} is due {}",
+                    course_name,
+                    assignment.name,
+                    format_date(assignment.duedate)
+                ),
+                message: format!(
+                    "{} in {} is due in {}, on {}",
+                    assignment.name,
+                    course_name,
+                    format_time_left(assignment.duedate - now),
+                    format_date(assignment.duedate)
+                ),
+                course: course_name,
+                changes: Vec::new(),
+                duedate: Some(assignment.duedate),
+                reminder: Some((assignment.cmid, unsent)),
+            });
+        }
+    }
+    Ok(notifications)
+}
+
+// Leads whose window before `duedate` has begun
+fn due_leads(duedate: i64, now: i64, leads: &[i64]) -> Vec<i64> {
+    if duedate <= now {
+        return Vec::new();
+    }
+    leads
+        .iter()
+        .copied()
+        .filter(|lead| duedate - now <= *lead)
+        .collect()
+}
+
+// Leads whose reminder wasn't sent yet
+fn unsent_leads(conn: &Connection, cmid: i64, duedate: i64, leads: &[i64]) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT EXISTS (SELECT 1 FROM Reminders WHERE cmid = ?1 AND duedate = ?2 AND lead = ?3)",
+    )?;
+    let mut unsent = Vec::new();
+    for lead in leads {
+        let sent: bool = stmt.query_row(params![cmid, duedate, lead], |row| row.get(0))?;
+        if !sent {
+            unsent.push(*lead);
+        }
+    }
+    Ok(unsent)
+}
+
+fn mark_reminders_sent(conn: &Connection, cmid: i64, duedate: i64, leads: &[i64]) -> Result<()> {
+    for lead in leads {
+        conn.execute(
+            "INSERT OR IGNORE INTO Reminders (cmid, duedate, lead, sentat)
+                VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)",
+            params![cmid, duedate, lead],
+        )?;
+    }
+    Ok(())
+}
+
+fn format_time_left(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    match minutes {
+        m if m >= 48 * 60 => format!("{} days", m / (24 * 60)),
+        m if m >= 60 => format!("{}h", m / 60),
+        m => format!("{}m", m),
+    }
+}
+
+fn course_name(config: &Configs, id: i64) -> String {
+    config
+        .get_course_name(id)
+        .cloned()
+        .unwrap_or_else(|| id.to_string())
+}
+
+async fn run_command(command: &[String], notification: &Notification) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| eyre!("No command to run"))?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("Failed to open stdin of '{}'", program))?;
+    stdin
+        .write_all(serde_json::to_string(notification)?.as_bytes())
+        .await?;
+    drop(stdin);
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(eyre!("'{}' exited with {}", program, status));
+    }
+    Ok(())
+}
+
+async fn post_webhook(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    notification: &Notification,
+) -> Result<()> {
+    let request = client.post(&webhook.url);
+    let request = match webhook.format {
+        WebhookFormat::Json => request.json(notification),
+        // Headers can't hold every title, ntfy takes it as a query parameter too
+        WebhookFormat::Ntfy => request
+            .query(&[("title", &notification.title)])
+            .body(notification.message.clone()),
+        WebhookFormat::Gotify => request.json(&serde_json::json!({
+            "title": notification.title,
+            "message": notification.message,
+            "priority": 5,
+        })),
+        WebhookFormat::Discord => {
+            let content = format!("**{}**\n{}", notification.title, notification.message);
+            let content: String = content.chars().take(DISCORD_LIMIT).collect();
+            request.json(&serde_json::json!({ "content": content }))
+        }
+    };
+    // The url holds the webhook's secret or topic, errors only name the host
+    let host = reqwest::Url::parse(&webhook.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(reqwest::Error::without_url)
+        .wrap_err_with(|| format!("Failed to post to {}", host))?;
+    Ok(())
+}
+
+async fn send_email(email: &EmailConfig, notification: &Notification) -> Result<()> {
+    let message = Message::builder()
+        .from(email.from.parse()?)
+        .to(email.to.parse()?)
+        .subject(&notification.title)
+        .header(ContentType::TEXT_PLAIN)
+        .body(notification.message.clone())?;
+
+    let mut transport = match email.tls {
+        EmailTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.server)?,
+        EmailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&email.server)?,
+        EmailTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&email.server),
+    };
+    if let Some(port) = email.port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport.build().send(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reminds_once_the_lead_window_begins() {
+        let hour = 60 * 60;
+        let leads = [24 * hour, hour];
+        assert!(due_leads(100 * hour, 50 * hour, &leads).is_empty());
+        assert_eq!(due_leads(100 * hour, 80 * hour, &leads), [24 * hour]);
+        assert_eq!(
+            due_leads(100 * hour, 100 * hour - 30 * 60, &leads),
+            [24 * hour, hour]
+        );
+        assert!(due_leads(100 * hour, 101 * hour, &leads).is_empty());
+        assert_eq!(format_time_left(23 * hour + 59 * 60), "23h");
+        assert_eq!(format_time_left(72 * hour), "3 days");
+    }
+
+    #[tokio::test]
+    async fn webhook_errors_leave_out_the_url() {
+        let webhook = WebhookConfig {
+            url: String::from("http://127.0.0.1:9/secret-topic"),
+            format: WebhookFormat::Ntfy,
+        };
+        let notification = Notification {
+            kind: NotificationKind::Changes,
+            courseid: 1,
+            course: String::from("TEST"),
+            title: String::from("TEST: 1 added"),
+            message: String::new(),
+            changes: Vec::new(),
+            duedate: None,
+            reminder: None,
+        };
+        let error = post_webhook(&reqwest::Client::new(), &webhook, &notification)
+            .await
+            .unwrap_err();
+        assert!(!format!("{:?}", error).contains("secret-topic"));
+        assert!(format!("{:?}", error).contains("127.0.0.1"));
+    }
+
+    #[test]
+    fn reminders_count_as_sent_once_marked() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::db::create_tables(&conn).unwrap();
+        let leads = [86400, 3600];
+        assert_eq!(unsent_leads(&conn, 7, 1000, &leads).unwrap(), leads);
+
+        mark_reminders_sent(&conn, 7, 1000, &leads[..1]).unwrap();
+        assert_eq!(unsent_leads(&conn, 7, 1000, &leads).unwrap(), [3600]);
+        // An extended deadline is reminded of again
+        assert_eq!(unsent_leads(&conn, 7, 2000, &leads).unwrap(), leads);
+    }
+}
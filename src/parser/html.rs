@@ -0,0 +1,819 @@
+// parser/html.rs
+//
+// HTML conversion over the `scraper` DOM, to either Markdown (CommonMark
+// plus GFM tables and strikethrough) or Org markup
+use ego_tree::NodeRef;
+use scraper::{node::Node, ElementRef, Html};
+use std::collections::HashMap;
+
+pub struct HtmlConverter<'a> {
+    local_paths: &'a HashMap<String, String>,
+    flavor: Flavor,
+    heading_depth: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Flavor {
+    Markdown,
+    Org,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Context {
+    in_table: bool,
+}
+
+impl<'a> HtmlConverter<'a> {
+    /// `local_paths` maps urls of downloaded files to the relative path used in their place
+    pub fn markdown(local_paths: &'a HashMap<String, String>) -> Self {
+        Self {
+            local_paths,
+            flavor: Flavor::Markdown,
+            heading_depth: 0,
+        }
+    }
+
+    /// Org headings are nested `heading_depth` levels below the enclosing outline
+    pub fn org(local_paths: &'a HashMap<String, String>, heading_depth: usize) -> Self {
+        Self {
+            local_paths,
+            flavor: Flavor::Org,
+            heading_depth,
+        }
+    }
+
+    pub fn convert(&self, html: &str) -> String {
+        let fragment = Html::parse_fragment(html);
+        let converted = self.children(*fragment.root_element(), Context::default());
+        normalize(&converted)
+    }
+
+    fn resolve_url(&self, url: &str) -> String {
+        match (self.flavor, self.local_paths.get(url)) {
+            (Flavor::Markdown, Some(path)) => markdown_target(path),
+            (Flavor::Markdown, None) => markdown_target(url),
+            (Flavor::Org, Some(path)) => format!("file:{}", path),
+            (Flavor::Org, None) => url.to_string(),
+        }
+    }
+
+    fn format_link(&self, text: &str, url: &str) -> String {
+        match self.flavor {
+            Flavor::Markdown => format!("[{}]({})", text, self.resolve_url(url)),
+            Flavor::Org => format!("[[{}][{}]]", self.resolve_url(url), org_description(text)),
+        }
+    }
+
+    fn emphasis(&self, tag: &str, content: &str) -> String {
+        let marker = match (self.flavor, tag) {
+            (Flavor::Markdown, "strong") => "**",
+            (Flavor::Markdown, "em") => "_",
+            (Flavor::Markdown, _) => "~~",
+            (Flavor::Org, "strong") => "*",
+            (Flavor::Org, "em") => "/",
+            (Flavor::Org, _) => "+",
+        };
+        wrap(marker, content)
+    }
+
+    fn children(&self, node: NodeRef<Node>, ctx: Context) -> String {
+        node.children().map(|child| self.node(child, ctx)).collect()
+    }
+
+    fn node(&self, node: NodeRef<Node>, ctx: Context) -> String {
+        match node.value() {
+            Node::Text(text) if self.flavor == Flavor::Org => {
+                org_escape(&collapse_whitespace(text))
+            }
+            Node::Text(text) => escape(&collapse_whitespace(text)),
+            Node::Element(_) => self.element(ElementRef::wrap(node).unwrap(), ctx),
+            _ => String::new(),
+        }
+    }
+
+    fn element(&self, element: ElementRef, ctx: Context) -> String {
+        let tag = element.value().name();
+        match tag {
+            "script" | "style" | "head" | "title" | "noscript" | "template" => String::new(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.heading(element, ctx),
+            "p" | "div" | "section" | "article" | "header" | "footer" | "main" | "nav"
+            | "aside" | "figure" | "figcaption" | "address" | "center" | "dl" | "dd" | "li" => {
+                block(&self.children(*element, ctx))
+            }
+            "dt" => block(&self.emphasis("strong", &self.children(*element, ctx))),
+            "strong" | "b" => self.emphasis("strong", &self.children(*element, ctx)),
+            "em" | "i" | "cite" | "dfn" => self.emphasis("em", &self.children(*element, ctx)),
+            "del" | "s" | "strike" => self.emphasis("del", &self.children(*element, ctx)),
+            "a" => self.link(element, ctx),
+            "img" => self.image(element),
+            "iframe" | "video" | "audio" | "embed" => self.embed(element),
+            "ul" | "ol" => self.list(element, ctx),
+            "table" => self.table(element, ctx),
+            _ if self.flavor == Flavor::Org => self.org_element(element, ctx),
+            _ => self.markdown_element(element, ctx),
+        }
+    }
+
+    fn markdown_element(&self, element: ElementRef, ctx: Context) -> String {
+        let tag = element.value().name();
+        match tag {
+            "br" if ctx.in_table => "<br>".to_string(),
+            "br" => "\\\n".to_string(),
+            "hr" => "\n\n---\n\n".to_string(),
+            "sup" | "sub" => format!("<{0}>{1}</{0}>", tag, self.children(*element, ctx)),
+            "code" | "kbd" | "samp" | "tt" => inline_code(&element.text().collect::<String>()),
+            "pre" => code_block(element),
+            "blockquote" => blockquote(&self.children(*element, ctx)),
+            _ => self.children(*element, ctx),
+        }
+    }
+
+    // Line breaks become plain newlines, Org's `\\` would be left dangling
+    // wherever Moodle's editor ends a paragraph with a `<br>`
+    fn org_element(&self, element: ElementRef, ctx: Context) -> String {
+        match element.value().name() {
+            "br" if ctx.in_table => " ".to_string(),
+            "br" => "\n".to_string(),
+            "hr" => "\n\n-----\n\n".to_string(),
+            "sup" => format!("^{{{}}}", self.children(*element, ctx)),
+            "sub" => format!("_{{{}}}", self.children(*element, ctx)),
+            "code" | "kbd" | "samp" | "tt" => org_inline_code(&element.text().collect::<String>()),
+            "pre" => org_code_block(element),
+            "blockquote" => org_blockquote(&self.children(*element, ctx)),
+            _ => self.children(*element, ctx),
+        }
+    }
+
+    // Moodle editors start section headings at h3, shift them up one level
+    fn heading(&self, element: ElementRef, ctx: Context) -> String {
+        let level: usize = element.value().name()[1..].parse().unwrap_or(1);
+        let content = self.children(*element, ctx).replace("\\\n", " ");
+        let content = collapse_whitespace(&content);
+        let content = content.trim();
+
+        let marker = match self.flavor {
+            Flavor::Markdown => "#".repeat(level.max(2) - 1),
+            Flavor::Org => "*".repeat(self.heading_depth + level.max(2) - 1),
+        };
+        if content.is_empty() {
+            String::new()
+        } else {
+            format!("\n\n{} {}\n\n", marker, content)
+        }
+    }
+
+    fn link(&self, element: ElementRef, ctx: Context) -> String {
+        let content = self.children(*element, ctx);
+        let text = content.trim();
+        match element.value().attr("href") {
+            Some(href) if !href.starts_with('#') && !is_script(href) => {
+                if text.is_empty() {
+                    String::new()
+                } else {
+                    self.format_link(text, href)
+                }
+            }
+            _ => content,
+        }
+    }
+
+    fn image(&self, element: ElementRef) -> String {
+        match element.value().attr("src") {
+            // Org displays links to images inline when they have no description
+            Some(src) if self.flavor == Flavor::Org => format!("[[{}]]", self.resolve_url(src)),
+            Some(src) => {
+                let alt = element.value().attr("alt").unwrap_or_default();
+                format!(
+                    "![{}]({})",
+                    escape(&collapse_whitespace(alt)),
+                    self.resolve_url(src)
+                )
+            }
+            None => String::new(),
+        }
+    }
+
+    // Embedded players can't work offline, leave a link to the media instead
+    fn embed(&self, element: ElementRef) -> String {
+        let src = element.value().attr("src").or_else(|| {
+            element
+                .children()
+                .filter_map(ElementRef::wrap)
+                .find(|child| child.value().name() == "source")
+                .and_then(|source| source.value().attr("src"))
+        });
+
+        match src {
+            Some(src) => {
+                let title = element
+                    .value()
+                    .attr("title")
+                    .map(collapse_whitespace)
+                    .map(|title| match self.flavor {
+                        Flavor::Markdown => escape(&title),
+                        Flavor::Org => org_escape(&title),
+                    })
+                    .unwrap_or_else(|| format!("Embedded {}", element.value().name()));
+                self.format_link(&title, src)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn list(&self, element: ElementRef, ctx: Context) -> String {
+        let ordered = element.value().name() == "ol";
+        let start: usize = element
+            .value()
+            .attr("start")
+            .and_then(|start| start.parse().ok())
+            .unwrap_or(1);
+
+        let mut items: Vec<String> = Vec::new();
+        for child in element.children() {
+            let Some(child_element) = ElementRef::wrap(child) else {
+                continue;
+            };
+            match child_element.value().name() {
+                "li" => items.push(tighten(&self.children(child, ctx))),
+                // Lists pasted from word processors nest sublists next to their items
+                "ul" | "ol" => match items.last_mut() {
+                    Some(last) => {
+                        last.push('\n');
+                        last.push_str(&tighten(&self.list(child_element, ctx)));
+                    }
+                    None => items.push(tighten(&self.list(child_element, ctx))),
+                },
+                _ => {}
+            }
+        }
+
+        // Org reads a leading `*` as a heading, its bullets are dashes
+        let bullet = match self.flavor {
+            Flavor::Markdown => "* ",
+            Flavor::Org => "- ",
+        };
+        let lines: Vec<String> = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if ordered {
+                    format!("{}. ", start + i)
+                } else {
+                    bullet.to_string()
+                };
+                indent_item(&marker, item)
+            })
+            .collect();
+
+        if lines.is_empty() {
+            String::new()
+        } else {
+            format!("\n\n{}\n\n", lines.join("\n"))
+        }
+    }
+
+    fn table(&self, element: ElementRef, ctx: Context) -> String {
+        let cell_ctx = Context { in_table: true };
+        let mut caption = String::new();
+        let mut rows: Vec<Vec<String>> = Vec::new();
+
+        for child in element.children().filter_map(ElementRef::wrap) {
+            let row_elements: Vec<ElementRef> = match child.value().name() {
+                "caption" => {
+                    caption = block(&self.children(*child, ctx));
+                    continue;
+                }
+                "tr" => vec![child],
+                "thead" | "tbody" | "tfoot" => child
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|row| row.value().name() == "tr")
+                    .collect(),
+                _ => continue,
+            };
+
+            for row in row_elements {
+                let mut cells = Vec::new();
+                for cell in row.children().filter_map(ElementRef::wrap) {
+                    let name = cell.value().name();
+                    if name != "td" && name != "th" {
+                        continue;
+                    }
+                    cells.push(table_cell(&self.children(*cell, cell_ctx), self.flavor));
+
+                    let colspan: usize = cell
+                        .value()
+                        .attr("colspan")
+                        .and_then(|span| span.parse().ok())
+                        .unwrap_or(1);
+                    cells.extend(std::iter::repeat_n(String::new(), colspan.max(1) - 1));
+                }
+                if !cells.is_empty() {
+                    rows.push(cells);
+                }
+            }
+        }
+
+        if rows.is_empty() {
+            return caption;
+        }
+
+        // GFM tables need exactly one header row, the first row stands in when absent
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        for row in rows.iter_mut() {
+            row.resize(columns, String::new());
+        }
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+
+        let format_row = |row: &Vec<String>| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| {
+                    let padding = width - cell.chars().count();
+                    format!("{}{}", cell, " ".repeat(padding))
+                })
+                .collect();
+            format!("| {} |", cells.join(" | "))
+        };
+
+        let mut lines = vec![format_row(&rows[0])];
+        let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        match self.flavor {
+            Flavor::Markdown => lines.push(format!("| {} |", separator.join(" | "))),
+            Flavor::Org => lines.push(format!("|-{}-|", separator.join("-+-"))),
+        }
+        lines.extend(rows[1..].iter().map(format_row));
+
+        format!("{}\n\n{}\n\n", caption, lines.join("\n"))
+    }
+}
+
+fn is_script(href: &str) -> bool {
+    href.trim_start()
+        .get(..11)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("javascript:"))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut previous_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !previous_space {
+                collapsed.push(' ');
+            }
+            previous_space = true;
+        } else {
+            collapsed.push(c);
+            previous_space = false;
+        }
+    }
+    collapsed
+}
+
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        let next = chars.get(i + 1).copied();
+        let needs_escape = match c {
+            '\\' | '*' | '`' | '[' | ']' => true,
+            // Underscores inside words never start emphasis
+            '_' => {
+                !(prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric))
+            }
+            // Text at the start of a line that reads like a heading, quote or list
+            '#' | '>' => chars[..i].iter().all(|c| c.is_whitespace()),
+            '-' | '+' => {
+                chars[..i].iter().all(|c| c.is_whitespace())
+                    && next.is_none_or(|next| next.is_whitespace() || next == c)
+            }
+            '.' | ')' => {
+                let start = chars[..i].iter().take_while(|c| c.is_whitespace()).count();
+                let digits = &chars[start..i];
+                !digits.is_empty()
+                    && digits.len() <= 9
+                    && digits.iter().all(char::is_ascii_digit)
+                    && next.is_none_or(char::is_whitespace)
+            }
+            // Text that reads like a tag or autolink would be taken as raw HTML
+            '<' => next.is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')),
+            _ => false,
+        };
+        if needs_escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Org has no escape character, a zero width space keeps text at the start
+// of a line from reading as a heading or keyword
+pub(crate) fn org_escape(text: &str) -> String {
+    let trimmed = text.trim_start();
+    let stars = trimmed.chars().take_while(|&c| c == '*').count();
+    let heading = stars > 0
+        && trimmed[stars..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace);
+    if heading || trimmed.starts_with("#+") {
+        let indent = &text[..text.len() - trimmed.len()];
+        format!("{}\u{200B}{}", indent, trimmed)
+    } else {
+        text.to_string()
+    }
+}
+
+// Brackets would end an Org link early. A lone image link is kept, Org
+// shows it in place of the description.
+pub(crate) fn org_description(text: &str) -> String {
+    match text
+        .strip_prefix("[[")
+        .and_then(|inner| inner.strip_suffix("]]"))
+    {
+        Some(image) if !image.contains(['[', ']']) => image.to_string(),
+        _ => text.replace('[', "(").replace(']', ")"),
+    }
+}
+
+fn wrap(marker: &str, content: &str) -> String {
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return content.to_string();
+    }
+    let leading = if content.starts_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    let trailing = if content.ends_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+// Hard breaks at the edges of a block render as stray backslashes
+fn trim_breaks(content: &str) -> &str {
+    let mut trimmed = content.trim();
+    loop {
+        let next = trimmed
+            .strip_prefix('\\')
+            .filter(|rest| rest.starts_with('\n'))
+            .or_else(|| {
+                trimmed
+                    .strip_suffix('\\')
+                    .filter(|rest| !rest.ends_with('\\'))
+            })
+            .map(str::trim);
+        match next {
+            Some(next) => trimmed = next,
+            None => return trimmed,
+        }
+    }
+}
+
+fn block(content: &str) -> String {
+    let trimmed = trim_breaks(content);
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n{}\n\n", trimmed)
+    }
+}
+
+// List items are kept tight, blank lines between their blocks are dropped.
+// Code blocks are left as they are.
+fn tighten(content: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+
+    for line in trim_breaks(content).lines() {
+        if in_fence {
+            lines.push(line);
+            if closes_code(line) {
+                in_fence = false;
+            }
+            continue;
+        }
+
+        let line = trim_single_space(line.trim_end());
+        if opens_code(line) {
+            in_fence = true;
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+fn indent_item(marker: &str, item: &str) -> String {
+    let indent = " ".repeat(marker.len());
+    item.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                format!("{}{}", marker, line)
+            } else if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn blockquote(content: &str) -> String {
+    let content = normalize(content);
+    if content.is_empty() {
+        return String::new();
+    }
+    let quoted: Vec<String> = content
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                ">".to_string()
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect();
+    format!("\n\n{}\n\n", quoted.join("\n"))
+}
+
+fn table_cell(content: &str, flavor: Flavor) -> String {
+    let lines = trim_breaks(content)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    match flavor {
+        Flavor::Markdown => lines.join("<br>").replace('|', "\\|"),
+        Flavor::Org => lines.join(" ").replace('|', "\\vert{}"),
+    }
+}
+
+// Markdown link destinations can't contain whitespace unless bracketed
+pub fn markdown_target(url: &str) -> String {
+    if url.contains(char::is_whitespace) {
+        format!("<{}>", url)
+    } else {
+        url.to_string()
+    }
+}
+
+fn inline_code(code: &str) -> String {
+    let code = collapse_whitespace(code);
+    if code.trim().is_empty() {
+        return code;
+    }
+    let fence = "`".repeat(longest_run(&code, '`') + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+fn code_block(element: ElementRef) -> String {
+    let code: String = element.text().collect();
+    let code = code.trim_matches('\n');
+
+    let language = std::iter::once(element)
+        .chain(element.children().filter_map(ElementRef::wrap))
+        .flat_map(|e| e.value().classes())
+        .find_map(|class| class.strip_prefix("language-"))
+        .unwrap_or_default();
+
+    let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+    format!("\n\n{}{}\n{}\n{}\n\n", fence, language, code, fence)
+}
+
+fn org_inline_code(code: &str) -> String {
+    let code = collapse_whitespace(code);
+    if code.trim().is_empty() {
+        code
+    } else {
+        format!("~{}~", code)
+    }
+}
+
+fn org_code_block(element: ElementRef) -> String {
+    let code: String = element.text().collect();
+    let code = code.trim_matches('\n');
+
+    let language = std::iter::once(element)
+        .chain(element.children().filter_map(ElementRef::wrap))
+        .flat_map(|e| e.value().classes())
+        .find_map(|class| class.strip_prefix("language-"));
+
+    // Lines that would read as headings or keywords are escaped with a comma
+    let code: Vec<String> = code
+        .lines()
+        .map(|line| {
+            if line.starts_with('*') || line.trim_start().starts_with("#+") {
+                format!(",{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+
+    match language {
+        Some(language) => format!(
+            "\n\n#+begin_src {}\n{}\n#+end_src\n\n",
+            language,
+            code.join("\n")
+        ),
+        None => format!(
+            "\n\n#+begin_example\n{}\n#+end_example\n\n",
+            code.join("\n")
+        ),
+    }
+}
+
+fn org_blockquote(content: &str) -> String {
+    let content = normalize(content);
+    if content.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n#+begin_quote\n{}\n#+end_quote\n\n", content)
+    }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or(0)
+}
+
+// Inline content following a block or hard break keeps the single
+// space that separated the two in the HTML source
+fn trim_single_space(line: &str) -> &str {
+    match line.strip_prefix(' ') {
+        Some(rest) if !rest.starts_with(' ') => rest,
+        _ => line,
+    }
+}
+
+// Code is kept verbatim between Markdown fences and Org source or example blocks
+fn opens_code(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```")
+        || line.starts_with("#+begin_src")
+        || line.starts_with("#+begin_example")
+}
+
+fn closes_code(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("#+end_src") || line.starts_with("#+end_example")
+}
+
+fn normalize(converted: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+
+    for line in converted.lines() {
+        if in_fence {
+            lines.push(line);
+            if closes_code(line) {
+                in_fence = false;
+            }
+            continue;
+        }
+
+        let line = trim_single_space(line.trim_end());
+        if opens_code(line) {
+            in_fence = true;
+        }
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_paths() -> HashMap<String, String> {
+        HashMap::from([(
+            "https://moodle.example.edu/webservice/pluginfile.php/88/mod_page/content/3/diagram.png"
+                .to_string(),
+            "CS101/embedded/88-mod_page-content-3-diagram.png".to_string(),
+        )])
+    }
+
+    fn assert_golden(html: &str, expected: &str) {
+        let local_paths = local_paths();
+        let converted = HtmlConverter::markdown(&local_paths).convert(html);
+        assert_eq!(converted, expected.trim_end());
+    }
+
+    fn assert_golden_org(html: &str, expected: &str) {
+        let local_paths = local_paths();
+        let converted = HtmlConverter::org(&local_paths, 1).convert(html);
+        assert_eq!(converted, expected.trim_end());
+    }
+
+    #[test]
+    fn label() {
+        assert_golden(
+            include_str!("fixtures/label.html"),
+            include_str!("fixtures/label.md"),
+        );
+    }
+
+    #[test]
+    fn page_with_table() {
+        assert_golden(
+            include_str!("fixtures/page_table.html"),
+            include_str!("fixtures/page_table.md"),
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        assert_golden(
+            include_str!("fixtures/nested_lists.html"),
+            include_str!("fixtures/nested_lists.md"),
+        );
+    }
+
+    #[test]
+    fn code_quotes_and_images() {
+        assert_golden(
+            include_str!("fixtures/code_quotes_images.html"),
+            include_str!("fixtures/code_quotes_images.md"),
+        );
+    }
+
+    #[test]
+    fn code_in_list_items() {
+        assert_golden(
+            include_str!("fixtures/list_code.html"),
+            include_str!("fixtures/list_code.md"),
+        );
+    }
+
+    #[test]
+    fn escapes_text_that_reads_like_blocks() {
+        assert_golden(
+            "<p>- 5 points</p><p>+ bonus</p><p>2024. Results</p><p>3) three</p><p>> not a quote</p><p>--- not a rule</p>",
+            "\\- 5 points\n\n\\+ bonus\n\n2024\\. Results\n\n3\\) three\n\n\\> not a quote\n\n\\--- not a rule",
+        );
+        assert_golden(
+            "<p>Pages 10-12 + notes, 3.5 hours, 1.</p>",
+            "Pages 10-12 + notes, 3.5 hours, 1.",
+        );
+    }
+
+    #[test]
+    fn org_escapes_headings_keywords_and_link_brackets() {
+        assert_golden_org(
+            "<p>* not a heading</p><p>#+TITLE: not a keyword</p><p>*bold* stays</p>\
+             <p><a href=\"https://example.edu\">[PDF] slides</a></p>\
+             <p><a href=\"https://example.edu\"><img src=\"https://example.edu/a.png\"></a></p>",
+            "\u{200B}* not a heading\n\n\u{200B}#+TITLE: not a keyword\n\n*bold* stays\n\n\
+             [[https://example.edu][(PDF) slides]]\n\n\
+             [[https://example.edu][https://example.edu/a.png]]",
+        );
+    }
+
+    #[test]
+    fn org_page_with_table() {
+        assert_golden_org(
+            include_str!("fixtures/page_table.html"),
+            include_str!("fixtures/page_table.org"),
+        );
+    }
+
+    #[test]
+    fn org_code_quotes_and_images() {
+        assert_golden_org(
+            include_str!("fixtures/code_quotes_images.html"),
+            include_str!("fixtures/code_quotes_images.org"),
+        );
+    }
+}
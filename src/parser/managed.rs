@@ -0,0 +1,223 @@
+// parser/managed.rs
+//
+// Generated documents are split into regions between begin/end markers.
+// Regenerating a document replaces the regions and leaves everything the
+// user wrote outside of them in place.
+
+const BEGIN: &str = "<!-- moodl-rs:begin ";
+const END: &str = "<!-- moodl-rs:end ";
+const CLOSE: &str = " -->";
+
+enum Segment {
+    Text(String),
+    Region(String, String),
+}
+
+/// `content` wrapped in markers for the region `id`
+pub fn managed_region(id: &str, content: &str) -> String {
+    format!(
+        "{}{}{}\n{}\n{}{}{}\n",
+        BEGIN,
+        id,
+        CLOSE,
+        content.trim_end(),
+        END,
+        id,
+        CLOSE
+    )
+}
+
+pub fn has_managed_regions(document: &str) -> bool {
+    document.contains(BEGIN)
+}
+
+/// `existing` with its regions replaced by those of `generated`. Regions no
+/// longer generated are dropped, new ones follow the region they follow in
+/// `generated`.
+pub fn merge_managed(existing: &str, generated: &str) -> String {
+    let generated: Vec<(String, String)> = segments(generated)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Region(id, body) => Some((id, body)),
+            Segment::Text(_) => None,
+        })
+        .collect();
+
+    let mut merged: Vec<Segment> = segments(existing)
+        .into_iter()
+        .filter(|segment| match segment {
+            Segment::Region(id, _) => generated.iter().any(|(other, _)| other == id),
+            Segment::Text(_) => true,
+        })
+        .collect();
+
+    for (i, (id, body)) in generated.iter().enumerate() {
+        let position = merged
+            .iter()
+            .position(|segment| matches!(segment, Segment::Region(other, _) if other == id));
+        match position {
+            Some(position) => merged[position] = Segment::Region(id.clone(), body.clone()),
+            None => {
+                let insert_at = match i.checked_sub(1).map(|previous| &generated[previous].0) {
+                    Some(previous) => merged
+                        .iter()
+                        .position(|segment| {
+                            matches!(segment, Segment::Region(other, _) if other == previous)
+                        })
+                        .map_or(merged.len(), |position| position + 1),
+                    None => merged
+                        .iter()
+                        .position(|segment| matches!(segment, Segment::Region(..)))
+                        .unwrap_or(merged.len()),
+                };
+                merged.insert(insert_at, Segment::Region(id.clone(), body.clone()));
+            }
+        }
+    }
+
+    let mut document = String::new();
+    for segment in merged {
+        match segment {
+            Segment::Text(text) => document.push_str(&text),
+            Segment::Region(id, body) => {
+                // Keep regions on their own lines when the user removed a newline
+                if !document.is_empty() && !document.ends_with('\n') {
+                    document.push('\n');
+                }
+                document.push_str(&managed_region(&id, &body));
+            }
+        }
+    }
+    document
+}
+
+// An unterminated region is left as text, so nothing the user wrote is lost,
+// and the regions after it are still found
+fn segments(document: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = document;
+
+    while let Some(start) = rest.find(BEGIN) {
+        let after_begin = &rest[start + BEGIN.len()..];
+        let Some((id, body, after_end)) = region(after_begin) else {
+            log::warn!(
+                "Managed region '{}' has no end marker, leaving it as is",
+                after_begin.lines().next().unwrap_or_default()
+            );
+            text.push_str(&rest[..start + BEGIN.len()]);
+            rest = after_begin;
+            continue;
+        };
+
+        text.push_str(&rest[..start]);
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        segments.push(Segment::Region(id.to_string(), body.to_string()));
+        // The newline after an end marker belongs to the region
+        rest = after_end.strip_prefix('\n').unwrap_or(after_end);
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+// Id and body of the region whose begin marker precedes `after_begin`, and
+// what follows its end marker
+fn region(after_begin: &str) -> Option<(&str, &str, &str)> {
+    let id_end = after_begin.find(CLOSE)?;
+    let id = &after_begin[..id_end];
+    let end_marker = format!("{}{}{}", END, id, CLOSE);
+    let body_start = &after_begin[id_end + CLOSE.len()..];
+    let body_end = body_start.find(&end_marker)?;
+    Some((
+        id,
+        body_start[..body_end].trim_start_matches('\n'),
+        &body_start[body_end + end_marker.len()..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(parts: &[(&str, &str)]) -> String {
+        parts
+            .iter()
+            .map(|(id, body)| managed_region(id, body))
+            .collect()
+    }
+
+    #[test]
+    fn replaces_regions_and_keeps_user_text_around_them() {
+        let existing = format!(
+            "My notes\n{}Between\n{}The end\n",
+            managed_region("a", "old a"),
+            managed_region("b", "old b")
+        );
+        let generated = document(&[("a", "new a"), ("b", "new b")]);
+        assert_eq!(
+            merge_managed(&existing, &generated),
+            format!(
+                "My notes\n{}Between\n{}The end\n",
+                managed_region("a", "new a"),
+                managed_region("b", "new b")
+            )
+        );
+    }
+
+    #[test]
+    fn drops_regions_no_longer_generated() {
+        let existing = format!(
+            "{}Kept\n{}",
+            managed_region("a", "a"),
+            managed_region("b", "b")
+        );
+        let generated = document(&[("a", "a")]);
+        assert_eq!(
+            merge_managed(&existing, &generated),
+            format!("{}Kept\n", managed_region("a", "a"))
+        );
+    }
+
+    #[test]
+    fn inserts_new_regions_after_the_one_they_follow() {
+        let existing = format!(
+            "{}Note on a\n{}",
+            managed_region("a", "a"),
+            managed_region("c", "c")
+        );
+        let generated = document(&[("a", "a"), ("b", "b"), ("c", "c")]);
+        assert_eq!(
+            merge_managed(&existing, &generated),
+            format!(
+                "{}{}Note on a\n{}",
+                managed_region("a", "a"),
+                managed_region("b", "b"),
+                managed_region("c", "c")
+            )
+        );
+    }
+
+    #[test]
+    fn unterminated_region_stays_text_and_later_regions_merge() {
+        let existing = format!(
+            "{}a{}\nhalf written\n{}",
+            BEGIN,
+            CLOSE,
+            managed_region("b", "old b")
+        );
+        let generated = document(&[("b", "new b")]);
+        let merged = merge_managed(&existing, &generated);
+        assert_eq!(
+            merged,
+            existing.replace(&managed_region("b", "old b"), &managed_region("b", "new b"))
+        );
+        // Merging again changes nothing
+        assert_eq!(merge_managed(&merged, &generated), merged);
+    }
+}
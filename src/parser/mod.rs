@@ -0,0 +1,288 @@
+// parser/mod.rs
+//
+pub mod html;
+pub mod managed;
+pub mod org;
+pub mod template;
+pub mod vault;
+
+use crate::{
+    models::{
+        changes::{Change, ChangeKind},
+        grades::GradeItem,
+        urls::UrlModule,
+    },
+    utils::relative_path,
+};
+use chrono::{Local, TimeZone};
+use eyre::Result;
+use html::{markdown_target, HtmlConverter};
+use managed::{has_managed_regions, merge_managed};
+use scraper::{Html, Selector};
+use std::{
+    collections::HashMap,
+    fs::{self, File as StdFile},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+fn parse_html(html: &str, local_paths: &HashMap<String, String>) -> String {
+    HtmlConverter::markdown(local_paths).convert(html)
+}
+
+/// Urls of files uploaded to Moodle that are embedded or linked in `html`
+pub fn collect_pluginfile_urls(html: &str) -> Vec<String> {
+    let fragment = Html::parse_fragment(html);
+    let selector = Selector::parse("img[src], a[href]").unwrap();
+
+    let mut urls: Vec<String> = Vec::new();
+    for element in fragment.select(&selector) {
+        let url = element
+            .value()
+            .attr("src")
+            .or_else(|| element.value().attr("href"))
+            .unwrap_or_default();
+
+        if url.contains("/pluginfile.php/") && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+pub fn is_variation_of(short: &str, long: &str) -> bool {
+    if !short.is_empty() || !long.is_empty() {
+        let short_cleaned = short.trim_end_matches("...");
+        let long_cleaned = long.replace("**", "").replace('\\', "");
+        long_cleaned.contains(short_cleaned)
+    } else {
+        false
+    }
+}
+
+pub fn parse_grades(grades: &[GradeItem]) -> String {
+    let mut max_name_len = 9;
+
+    grades.iter().for_each(|grade| {
+        if let Some(name) = grade.display_name() {
+            max_name_len = max_name_len.max(name.chars().count());
+        }
+    });
+
+    let max_grade_len = 5;
+    let max_date_len = 19;
+    let max_range_len = 12;
+    let max_percent_len = 8;
+
+    let mut markdown = format!(
+        "# Grades: \n\n| {:<name_width$} | {:<date_width$} | {:<range_width$} | {:<grade_width$} | {:<percent_width$} |\n",
+        "Item Name",
+        "Graded On",
+        "Range",
+        "Grade",
+        "Percent",
+        name_width = max_name_len,
+        date_width = max_date_len,
+        range_width = max_range_len,
+        grade_width = max_grade_len,
+        percent_width = max_percent_len,
+    );
+    markdown.push_str(&format!(
+        "|{:-<name_dashes$}|{:-<date_dashes$}|{:-<range_dashes$}|{:-<grade_dashes$}|{:-<percent_dashes$}|\n",
+        "",
+        "",
+        "",
+        "",
+        "",
+        name_dashes = max_name_len + 2,
+        date_dashes = max_date_len + 2,
+        range_dashes = max_range_len + 2,
+        grade_dashes = max_grade_len + 2,
+        percent_dashes = max_percent_len + 2,
+    ));
+
+    grades.iter().for_each(|grade| {
+        if let Some(name) = grade.display_name() {
+
+            let grade_val = grade.grade();
+
+            let grade_date = if let Some(date) = &grade.gradedategraded {
+                parse_date(*date)
+            } else {
+                String::from("N/A")
+            };
+
+            let grade_range = grade.range();
+
+            markdown.push_str(&format!(
+                "| {:<name_width$} | {:<date_width$} | {:<range_width$} | {:<grade_width$} | {:<percent_width$} |\n",
+                name,
+                grade_date,
+                grade_range,
+                grade_val,
+                format_percentage(grade.percentage()),
+                name_width = max_name_len,
+                date_width = max_date_len,
+                range_width = max_range_len,
+                grade_width = max_grade_len,
+                percent_width = max_percent_len,
+            ));
+        }
+    });
+
+    markdown
+}
+
+/// Percentages as Moodle shows them, `87.50 %`
+pub fn format_percentage(percentage: Option<f64>) -> String {
+    percentage.map_or_else(|| String::from("N/A"), |p| format!("{:.2} %", p))
+}
+
+/// Links to the files among `paths` that exist on disk, relative to `document_dir`
+pub fn resolve_local_links(
+    paths: &HashMap<String, PathBuf>,
+    document_dir: &Path,
+) -> HashMap<String, String> {
+    paths
+        .iter()
+        .filter(|(_, path)| path.exists())
+        .map(|(url, path)| (url.clone(), relative_path(document_dir, path)))
+        .collect()
+}
+
+pub fn parse_links(urls: Vec<UrlModule>, local_paths: &HashMap<String, String>) -> String {
+    let mut markdown = String::from("# Links\n\n");
+
+    urls.into_iter().for_each(|url| {
+        markdown.push_str(&format!("* [{}]({})", url.name, url.externalurl));
+
+        if let Some(path) = local_paths.get(&url.externalurl) {
+            markdown.push_str(&format!(" ([local copy]({}))", markdown_target(path)));
+        }
+        markdown.push('\n');
+
+        if let Some(intro) = &url.intro {
+            let parsed_intro = parse_html(intro, &HashMap::new());
+            parsed_intro.lines().for_each(|line| {
+                if line.is_empty() {
+                    markdown.push('\n');
+                } else {
+                    markdown.push_str(&format!("  {}\n", line));
+                }
+            });
+        }
+    });
+
+    markdown
+}
+
+/// Changelog of a course, the latest fetch first
+pub fn parse_changes(mut changes: Vec<Change>) -> String {
+    let mut markdown = String::from("# Changes\n");
+    let mut changeset = None;
+
+    // Newest fetch first, its changes in the order they were found
+    changes.sort_by_key(|change| std::cmp::Reverse(change.changeset));
+    changes.into_iter().for_each(|change| {
+        if changeset != Some(change.changeset) {
+            changeset = Some(change.changeset);
+            markdown.push_str(&format!(
+                "\n## {}\n\n",
+                change.detectedat.as_deref().unwrap_or("Unknown")
+            ));
+        }
+
+        let kind = match change.kind {
+            ChangeKind::Added => "Added",
+            ChangeKind::Removed => "Removed",
+            ChangeKind::Modified => "Modified",
+        };
+        markdown.push_str(&format!(
+            "- {} {} **{}**",
+            kind,
+            change.entity.as_str(),
+            change.name
+        ));
+        if let Some(detail) = &change.detail {
+            markdown.push_str(&format!(": {}", detail));
+        }
+        markdown.push('\n');
+    });
+
+    markdown
+}
+
+/// Writes `parsed_course` to `<file_path>.md`. When it has managed regions
+/// only those are replaced in an existing file, the rest is the user's.
+pub fn save_markdown_to_file(parsed_course: String, file_path: &str) -> Result<()> {
+    let file_path = format!("{}.md", file_path);
+    let content = match fs::read_to_string(&file_path) {
+        Ok(existing) if has_managed_regions(&existing) && has_managed_regions(&parsed_course) => {
+            merge_managed(&existing, &parsed_course)
+        }
+        // Written before regions existed, edits can't be told apart from generated text
+        Ok(existing) if has_managed_regions(&parsed_course) && !existing.is_empty() => {
+            let backup = format!("{}.bak", file_path);
+            fs::write(&backup, existing)?;
+            log::warn!(
+                "{} has no managed regions, saved a copy to {} before regenerating",
+                file_path,
+                backup
+            );
+            parsed_course
+        }
+        _ => parsed_course,
+    };
+
+    let mut file = StdFile::create(file_path)?;
+    Ok(file.write_all(content.as_bytes())?)
+}
+
+// Local time, as Org timestamps and the exports show dates
+fn parse_date(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .expect("Invalid timestamp")
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::changes::Entity;
+
+    fn change(changeset: i64, name: &str) -> Change {
+        Change {
+            changeset,
+            courseid: 1,
+            entity: Entity::Module,
+            key: name.to_string(),
+            kind: ChangeKind::Added,
+            name: name.to_string(),
+            detail: None,
+            detectedat: Some(format!("fetch {}", changeset)),
+        }
+    }
+
+    #[test]
+    fn lists_newest_fetch_first_in_the_order_found() {
+        let changes = vec![
+            change(1, "a"),
+            change(1, "b"),
+            change(2, "c"),
+            change(2, "d"),
+        ];
+        assert_eq!(
+            parse_changes(changes),
+            "# Changes\n\
+             \n## fetch 2\n\n\
+             - Added module **c**\n\
+             - Added module **d**\n\
+             \n## fetch 1\n\n\
+             - Added module **a**\n\
+             - Added module **b**\n"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+// parser/org.rs
+//
+// Org-mode rendering of a parsed course, sections and modules become an
+// outline so assignment deadlines show up in the agenda
+use crate::{
+    models::{assignments::Assignment, course::CourseSection, grades::GradeItem},
+    parser::{
+        format_percentage,
+        html::{org_description, org_escape, HtmlConverter},
+    },
+};
+use chrono::{Local, TimeZone};
+use eyre::Result;
+use std::{collections::HashMap, fs::File as StdFile, io::Write};
+
+fn parse_html(html: &str, local_paths: &HashMap<String, String>, heading_depth: usize) -> String {
+    HtmlConverter::org(local_paths, heading_depth).convert(html)
+}
+
+pub fn parse_course(
+    title: &str,
+    course: Vec<CourseSection>,
+    assignments: &[Assignment],
+    local_paths: &HashMap<String, String>,
+) -> String {
+    let mut org = format!("#+TITLE: {}\n\n", title);
+    let mut placed: Vec<i64> = Vec::new();
+
+    course.into_iter().for_each(|section| {
+        log::debug!("Section name: {}", section.name);
+
+        org.push_str(&format!("* {}\n\n", heading(&section.name)));
+        let summary = parse_html(&section.summary, local_paths, 1);
+        if !summary.is_empty() {
+            org.push_str(&format!("{}\n\n", summary));
+        }
+
+        section.modules.into_iter().for_each(|module| {
+            log::debug!("Module name: {:#?}", &module.name);
+
+            // Every module gets a heading, anything else would nest it under its sibling
+            org.push_str(&format!("** {}\n", heading(&module.name)));
+            if let Some(assignment) = assignments.iter().find(|a| a.cmid == module.id) {
+                if let Some(deadline) = parse_deadline(assignment.duedate) {
+                    org.push_str(&format!("DEADLINE: {}\n", deadline));
+                }
+                placed.push(assignment.id);
+            }
+            org.push('\n');
+
+            let parsed_desc = module
+                .description
+                .as_deref()
+                .map(|desc| parse_html(desc, local_paths, 2))
+                .unwrap_or_default();
+            if !parsed_desc.is_empty() {
+                org.push_str(&format!("{}\n\n", parsed_desc));
+            }
+
+            let is_link = module.modname.as_deref() == Some("url");
+            if let Some(files) = &module.contents {
+                files.iter().for_each(|file| {
+                    if let (Some(name), Some(url)) = (&file.filename, &file.fileurl) {
+                        let name = org_description(name);
+                        if let Some(path) = local_paths.get(url) {
+                            log::debug!("Module file: {} at {}", name, path);
+                            org.push_str(&format!("[[file:{}][{}]]\n\n", path, name));
+                        } else if is_link {
+                            org.push_str(&format!("[[{}][{}]]\n\n", url, name));
+                        } else {
+                            org.push_str(&format!("[[{}][{}]] /(not downloaded)/\n\n", url, name));
+                        }
+                    }
+                });
+            }
+        });
+    });
+
+    // Assignments whose module isn't in the course structure still belong in the agenda
+    let unplaced: Vec<&Assignment> = assignments
+        .iter()
+        .filter(|assignment| !placed.contains(&assignment.id))
+        .collect();
+    if !unplaced.is_empty() {
+        org.push_str("* Assignments\n\n");
+        unplaced.into_iter().for_each(|assignment| {
+            org.push_str(&format!("** {}\n", heading(&assignment.name)));
+            if let Some(deadline) = parse_deadline(assignment.duedate) {
+                org.push_str(&format!("DEADLINE: {}\n", deadline));
+            }
+            org.push('\n');
+
+            let intro = assignment
+                .intro
+                .as_deref()
+                .map(|intro| parse_html(intro, local_paths, 2))
+                .unwrap_or_default();
+            if !intro.is_empty() {
+                org.push_str(&format!("{}\n\n", intro));
+            }
+        });
+    }
+
+    org
+}
+
+pub fn parse_grades(grades: &[GradeItem]) -> String {
+    let header = [
+        "Item Name".to_string(),
+        "Graded On".to_string(),
+        "Range".to_string(),
+        "Grade".to_string(),
+        "Percent".to_string(),
+    ];
+    let mut rows = vec![header];
+
+    grades.iter().for_each(|grade| {
+        if let Some(name) = grade.display_name() {
+            let grade_val = grade.grade();
+
+            let grade_date = grade
+                .gradedategraded
+                .and_then(parse_timestamp)
+                .unwrap_or_else(|| String::from("N/A"));
+
+            let grade_range = grade.range();
+
+            rows.push([
+                name.replace('|', "\\vert{}"),
+                grade_date,
+                grade_range,
+                grade_val,
+                format_percentage(grade.percentage()),
+            ]);
+        }
+    });
+
+    let widths: Vec<usize> = (0..5)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let format_row = |row: &[String; 5]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut org = format!("* Grades\n\n{}", format_row(&rows[0]));
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    org.push_str(&format!("|-{}-|\n", separator.join("-+-")));
+    rows[1..]
+        .iter()
+        .for_each(|row| org.push_str(&format_row(row)));
+
+    org
+}
+
+pub fn save_org_to_file(parsed_course: String, file_path: &str) -> Result<()> {
+    let file_path = format!("{}.org", file_path);
+    let mut file = StdFile::create(file_path)?;
+    Ok(file.write_all(parsed_course.as_bytes())?)
+}
+
+// A heading ends at the line's end, and a name starting with stars or
+// `#+` would read as another heading or a keyword
+fn heading(name: &str) -> String {
+    org_escape(&name.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Active timestamp for a `DEADLINE:` line, Moodle uses 0 for no due date
+fn parse_deadline(timestamp: i64) -> Option<String> {
+    if timestamp <= 0 {
+        return None;
+    }
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("<%Y-%m-%d %a %H:%M>").to_string())
+}
+
+/// Inactive timestamp, shown without adding the entry to the agenda
+fn parse_timestamp(timestamp: i64) -> Option<String> {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("[%Y-%m-%d %a %H:%M]").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::course::{CourseFile, CourseModule};
+
+    #[test]
+    fn escapes_headings_and_file_descriptions() {
+        let section = CourseSection {
+            id: 1,
+            name: String::from("* Week 1\nIntro"),
+            summary: String::new(),
+            courseid: Some(1),
+            modules: vec![CourseModule {
+                id: 10,
+                name: String::from("#+TITLE: Slides"),
+                modname: Some(String::from("resource")),
+                instance: None,
+                contextid: None,
+                description: None,
+                contents: Some(vec![CourseFile {
+                    filename: Some(String::from("[Draft] notes.pdf")),
+                    filepath: None,
+                    fileurl: Some(String::from("https://moodle.example/notes.pdf")),
+                    timemodified: None,
+                    module_id: Some(10),
+                }]),
+                section_id: Some(1),
+                lastfetched: None,
+            }],
+            lastfetched: None,
+        };
+        let local_paths = HashMap::from([(
+            String::from("https://moodle.example/notes.pdf"),
+            String::from("course/notes.pdf"),
+        )]);
+
+        assert_eq!(
+            parse_course("Course", vec![section], &[], &local_paths),
+            "#+TITLE: Course\n\n\
+             * \u{200B}* Week 1 Intro\n\n\
+             ** \u{200B}#+TITLE: Slides\n\n\
+             [[file:course/notes.pdf][(Draft) notes.pdf]]\n\n"
+        );
+    }
+}
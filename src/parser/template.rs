@@ -0,0 +1,327 @@
+// parser/template.rs
+//
+// Course markdown rendered from a MiniJinja template. The built in template
+// gives the default output, a course can point `template` in the config at
+// its own. The context is documented in docs/templates.org.
+use crate::{
+    models::{
+        assignments::Assignment,
+        completion::ActivityCompletion,
+        course::CourseSection,
+        grades::{projected_percentage, GradeItem},
+    },
+    parser::{
+        format_percentage, html::markdown_target, is_variation_of, managed::managed_region,
+        parse_date,
+    },
+};
+use eyre::{Result, WrapErr};
+use minijinja::{path_loader, AutoEscape, Environment};
+use serde::Serialize;
+use std::{collections::HashMap, path::Path};
+
+pub const DEFAULT_COURSE_TEMPLATE: &str = include_str!("templates/course.md.jinja");
+
+#[derive(Serialize)]
+pub struct CourseContext {
+    course: CourseInfo,
+    sections: Vec<SectionContext>,
+    assignments: Vec<AssignmentContext>,
+    grades: GradesContext,
+}
+
+#[derive(Serialize)]
+struct CourseInfo {
+    id: i64,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct SectionContext {
+    id: i64,
+    name: String,
+    /// Converted to markdown
+    summary: String,
+    lastfetched: Option<String>,
+    modules: Vec<ModuleContext>,
+}
+
+#[derive(Serialize)]
+struct ModuleContext {
+    id: i64,
+    name: String,
+    modname: Option<String>,
+    /// Converted to markdown
+    description: String,
+    /// False when the description already starts with the name
+    show_name: bool,
+    due: Option<String>,
+    /// `complete`, `incomplete` or `failed` when completion is tracked
+    completion: Option<String>,
+    files: Vec<FileContext>,
+    lastfetched: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FileContext {
+    name: String,
+    url: String,
+    /// Link target of the downloaded copy, relative to the document
+    link: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AssignmentContext {
+    id: i64,
+    cmid: i64,
+    name: String,
+    due: Option<String>,
+    cutoff: Option<String>,
+    /// Converted to markdown
+    intro: String,
+}
+
+#[derive(Serialize)]
+struct GradesContext {
+    /// Width of the widest item name, at least that of the "Item Name" header
+    name_width: usize,
+    items: Vec<GradeContext>,
+    /// Course total in percent over the graded items, see `projected_percentage`
+    standing: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct GradeContext {
+    name: String,
+    itemtype: Option<String>,
+    module: Option<String>,
+    cmid: Option<i64>,
+    grade: String,
+    graderaw: Option<f64>,
+    grademin: f64,
+    grademax: f64,
+    range: String,
+    percentage: Option<f64>,
+    /// `percentage` formatted, or N/A
+    percent: String,
+    weight: Option<f64>,
+    /// As Moodle's grade report formats them
+    gradeformatted: Option<String>,
+    percentageformatted: Option<String>,
+    letter: Option<String>,
+    graded_on: String,
+    feedback: Option<String>,
+}
+
+pub fn course_context(
+    id: i64,
+    name: &str,
+    sections: Vec<CourseSection>,
+    assignments: &[Assignment],
+    grades: Vec<GradeItem>,
+    completion: &[ActivityCompletion],
+    local_paths: &HashMap<String, String>,
+) -> CourseContext {
+    let due = |timestamp: i64| (timestamp > 0).then(|| parse_date(timestamp));
+
+    let sections = sections
+        .into_iter()
+        .map(|section| SectionContext {
+            id: section.id,
+            summary: super::parse_html(&section.summary, local_paths),
+            name: section.name,
+            lastfetched: section.lastfetched,
+            modules: section
+                .modules
+                .into_iter()
+                .map(|module| {
+                    let description = module
+                        .description
+                        .as_deref()
+                        .map(|desc| super::parse_html(desc, local_paths))
+                        .unwrap_or_default();
+                    let files = module
+                        .contents
+                        .iter()
+                        .flatten()
+                        .filter_map(|file| match (&file.filename, &file.fileurl) {
+                            (Some(name), Some(url)) => Some(FileContext {
+                                name: name.clone(),
+                                url: url.clone(),
+                                link: local_paths.get(url).map(|path| markdown_target(path)),
+                            }),
+                            _ => None,
+                        })
+                        .collect();
+
+                    ModuleContext {
+                        id: module.id,
+                        show_name: !is_variation_of(&module.name, &description),
+                        due: assignments
+                            .iter()
+                            .find(|assignment| assignment.cmid == module.id)
+                            .and_then(|assignment| due(assignment.duedate)),
+                        completion: completion
+                            .iter()
+                            .find(|status| status.cmid == module.id)
+                            .and_then(|status| status.label())
+                            .map(String::from),
+                        name: module.name,
+                        modname: module.modname,
+                        description,
+                        files,
+                        lastfetched: module.lastfetched,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let assignments = assignments
+        .iter()
+        .map(|assignment| AssignmentContext {
+            id: assignment.id,
+            cmid: assignment.cmid,
+            name: assignment.name.clone(),
+            due: due(assignment.duedate),
+            cutoff: due(assignment.cutoffdate),
+            intro: assignment
+                .intro
+                .as_deref()
+                .map(|intro| super::parse_html(intro, local_paths))
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    let standing = projected_percentage(&grades, &HashMap::new());
+    let items: Vec<GradeContext> = grades
+        .into_iter()
+        .filter_map(|grade| {
+            Some(GradeContext {
+                name: grade.display_name()?.to_string(),
+                percentage: grade.percentage(),
+                percent: format_percentage(grade.percentage()),
+                weight: grade.weightraw,
+                grade: grade.grade(),
+                range: grade.range(),
+                letter: grade.letter().map(String::from),
+                gradeformatted: grade.gradeformatted,
+                percentageformatted: grade.percentageformatted,
+                itemtype: grade.itemtype,
+                module: grade.itemmodule,
+                cmid: grade.cmid,
+                graderaw: grade.graderaw,
+                grademin: grade.grademin,
+                grademax: grade.grademax,
+                graded_on: grade
+                    .gradedategraded
+                    .map_or_else(|| String::from("N/A"), parse_date),
+                feedback: grade.feedback,
+            })
+        })
+        .collect();
+
+    CourseContext {
+        course: CourseInfo {
+            id,
+            name: name.to_string(),
+        },
+        sections,
+        assignments,
+        grades: GradesContext {
+            name_width: items
+                .iter()
+                .map(|item| item.name.chars().count())
+                .fold("Item Name".len(), usize::max),
+            items,
+            standing,
+        },
+    }
+}
+
+/// Renders `context` with the template at `template`, or the default one.
+/// Templates can include others from their own directory.
+pub fn render_course(template: Option<&Path>, context: &CourseContext) -> Result<String> {
+    let mut env = Environment::new();
+    env.set_auto_escape_callback(|_| AutoEscape::None);
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    env.add_filter("managed", |content: String, id: String| {
+        managed_region(&id, &content)
+    });
+    env.add_filter("pad", pad);
+
+    let rendered = match template {
+        Some(path) => {
+            if let Some(dir) = path.parent() {
+                env.set_loader(path_loader(dir));
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            env.get_template(&name)
+                .and_then(|template| template.render(context))
+                .wrap_err_with(|| format!("Failed to render template {}", path.display()))?
+        }
+        None => env
+            .template_from_named_str("course.md.jinja", DEFAULT_COURSE_TEMPLATE)
+            .and_then(|template| template.render(context))
+            .wrap_err("Failed to render the default course template")?,
+    };
+    Ok(rendered)
+}
+
+// `value` left aligned in `width` columns, filled with `fill` or spaces
+fn pad(value: String, width: usize, fill: Option<String>) -> String {
+    let fill = fill.and_then(|fill| fill.chars().next()).unwrap_or(' ');
+    let len = value.chars().count();
+    let mut padded = value;
+    padded.extend(std::iter::repeat_n(fill, width.saturating_sub(len)));
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{managed::managed_region, parse_grades};
+
+    fn grades() -> Vec<GradeItem> {
+        [
+            (Some("Assignment 1"), "mod", Some(8.0), Some(1_696_000_000)),
+            (Some("A much longer quiz name"), "mod", None, None),
+            (None, "category", Some(7.25), None),
+            (None, "course", Some(7.25), None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, itemtype, graderaw, graded))| GradeItem {
+            id: i as i64,
+            itemname: name.map(String::from),
+            itemtype: Some(itemtype.to_string()),
+            itemmodule: None,
+            iteminstance: 0,
+            itemnumber: None,
+            idnumber: None,
+            categoryid: None,
+            cmid: None,
+            graderaw,
+            gradedatesubmitted: None,
+            gradedategraded: graded,
+            grademin: 0.0,
+            grademax: 10.0,
+            feedback: None,
+            courseid: None,
+            weightraw: None,
+            gradeformatted: None,
+            percentageformatted: None,
+            lettergradeformatted: None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn default_template_matches_grades_table() {
+        let context = course_context(1, "ONE", Vec::new(), &[], grades(), &[], &HashMap::new());
+        let rendered = render_course(None, &context).unwrap();
+        assert_eq!(rendered, managed_region("grades", &parse_grades(&grades())));
+    }
+}
@@ -0,0 +1,484 @@
+// parser/vault.rs
+//
+// Obsidian/Logseq vault output, a note per section and module wiki-linked
+// to a course index note. Everything below USER_NOTES_MARKER belongs to the
+// user and is carried over when the notes are regenerated, found by the ids
+// in the front matter when a note's name changed.
+use crate::{
+    models::{assignments::Assignment, course::CourseSection, grades::GradeItem},
+    parser::{parse_date, parse_grades, parse_html},
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const USER_NOTES_MARKER: &str = "<!-- moodl-rs: your notes below this line are kept -->";
+
+pub struct Note {
+    pub name: String,
+    pub content: String,
+    /// None for the course index, which is found by its name
+    pub subject: Option<NoteSubject>,
+}
+
+/// The section or module a note is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteSubject {
+    Section(i64),
+    Module(i64),
+}
+
+/// The course being written as notes
+pub struct VaultCourse<'a> {
+    pub id: i64,
+    pub name: &'a str,
+    /// Moodle host, as `api.base_url` in the config
+    pub base_url: &'a str,
+}
+
+#[derive(Serialize)]
+struct FrontMatter<'a> {
+    course: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    section: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    section_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_id: Option<i64>,
+    moodle_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lastfetched: Option<&'a str>,
+    tags: Vec<String>,
+}
+
+// The ids `FrontMatter` wrote to an existing note
+#[derive(Deserialize)]
+struct StoredIds {
+    section_id: Option<i64>,
+    module_id: Option<i64>,
+}
+
+/// Notes for `sections`. `local_paths` holds links relative to the notes
+/// directory, downloaded files are wiki-linked by their file name.
+pub fn parse_vault(
+    course: &VaultCourse,
+    sections: Vec<CourseSection>,
+    assignments: &[Assignment],
+    grades: &[GradeItem],
+    local_paths: &HashMap<String, String>,
+) -> Vec<Note> {
+    let course_tag = format!("course/{}", tag(course.name));
+    let shared_names = shared_names(course, &sections);
+    let mut notes = Vec::new();
+    let mut index = String::from("## Sections\n\n");
+
+    for section in sections {
+        let section_title = section_title(&section);
+        let section_note = unique_name(
+            &shared_names,
+            &format!("{} - {}", course.name, section_title),
+            section.id,
+        );
+        index.push_str(&format!("- {}\n", wiki_link(&section_note, &section_title)));
+
+        let mut body = format!(
+            "# {}\n\nCourse: {}\n\n",
+            section_title,
+            wiki_link(&note_name(course.name), course.name)
+        );
+        let summary = parse_html(&section.summary, local_paths);
+        if !summary.is_empty() {
+            body.push_str(&format!("{}\n\n", summary));
+        }
+
+        let mut in_list = false;
+        for module in section.modules {
+            let description = module
+                .description
+                .as_deref()
+                .map(|desc| parse_html(desc, local_paths))
+                .unwrap_or_default();
+
+            // Labels are text on the course page, they stay in the section note
+            if module.modname.as_deref() == Some("label") {
+                if !description.is_empty() {
+                    if in_list {
+                        body.push('\n');
+                        in_list = false;
+                    }
+                    body.push_str(&format!("{}\n\n", description));
+                }
+                continue;
+            }
+
+            let module_note = unique_name(
+                &shared_names,
+                &format!("{} - {}", course.name, module.name),
+                module.id,
+            );
+            body.push_str(&format!("- {}\n", wiki_link(&module_note, &module.name)));
+            in_list = true;
+
+            let mut module_body = format!(
+                "# {}\n\nCourse: {} · Section: {}\n\n",
+                module.name,
+                wiki_link(&note_name(course.name), course.name),
+                wiki_link(&section_note, &section_title)
+            );
+            if let Some(assignment) = assignments.iter().find(|a| a.cmid == module.id) {
+                if assignment.duedate > 0 {
+                    module_body.push_str(&format!("Due: {}\n\n", parse_date(assignment.duedate)));
+                }
+            }
+            if !description.is_empty() {
+                module_body.push_str(&format!("{}\n\n", description));
+            }
+
+            let is_link = module.modname.as_deref() == Some("url");
+            let files: Vec<String> = module
+                .contents
+                .iter()
+                .flatten()
+                .filter_map(|file| match (&file.filename, &file.fileurl) {
+                    (Some(name), Some(url)) => Some(match local_paths.get(url) {
+                        Some(path) => wiki_link(file_name(path), name),
+                        None if is_link => format!("[{}]({})", name, url),
+                        None => format!("[{}]({}) _(not downloaded)_", name, url),
+                    }),
+                    _ => None,
+                })
+                .collect();
+            if !files.is_empty() {
+                let heading = if is_link { "Link" } else { "Files" };
+                module_body.push_str(&format!("## {}\n\n", heading));
+                files
+                    .iter()
+                    .for_each(|file| module_body.push_str(&format!("- {}\n", file)));
+                module_body.push('\n');
+            }
+
+            let modname = module.modname.as_deref().unwrap_or("module");
+            let front_matter = FrontMatter {
+                course: course.name,
+                section: Some(&section_title),
+                section_id: Some(section.id),
+                module_id: Some(module.id),
+                moodle_url: module_url(course, modname, module.id),
+                lastfetched: module.lastfetched.as_deref(),
+                tags: vec![
+                    "moodle".to_string(),
+                    course_tag.clone(),
+                    format!("moodle/{}", tag(modname)),
+                ],
+            };
+            notes.push(Note {
+                name: module_note,
+                content: with_front_matter(&front_matter, &module_body),
+                subject: Some(NoteSubject::Module(module.id)),
+            });
+        }
+
+        let front_matter = FrontMatter {
+            course: course.name,
+            section: Some(&section_title),
+            section_id: Some(section.id),
+            module_id: None,
+            moodle_url: format!(
+                "https://{}/course/section.php?id={}",
+                course.base_url, section.id
+            ),
+            lastfetched: section.lastfetched.as_deref(),
+            tags: vec![
+                "moodle".to_string(),
+                course_tag.clone(),
+                "moodle/section".to_string(),
+            ],
+        };
+        notes.push(Note {
+            name: section_note,
+            content: with_front_matter(&front_matter, &body),
+            subject: Some(NoteSubject::Section(section.id)),
+        });
+    }
+
+    index.push('\n');
+    index.push_str(&parse_grades(grades));
+    let front_matter = FrontMatter {
+        course: course.name,
+        section: None,
+        section_id: None,
+        module_id: None,
+        moodle_url: format!(
+            "https://{}/course/view.php?id={}",
+            course.base_url, course.id
+        ),
+        lastfetched: None,
+        tags: vec![
+            "moodle".to_string(),
+            course_tag,
+            "moodle/course".to_string(),
+        ],
+    };
+    notes.push(Note {
+        name: note_name(course.name),
+        content: with_front_matter(&front_matter, &format!("# {}\n\n{}", course.name, index)),
+        subject: None,
+    });
+
+    notes
+}
+
+/// Writes `notes` to `dir`, keeping whatever follows the marker in existing
+/// notes. A section or module note renamed since is replaced by the new one.
+pub fn save_notes(notes: Vec<Note>, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut existing: HashMap<NoteSubject, PathBuf> = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "md") {
+            let content = fs::read_to_string(&path).unwrap_or_default();
+            if let Some(subject) = note_subject(&content) {
+                existing.insert(subject, path);
+            }
+        }
+    }
+
+    // Everything is read before writing, a new note may take an old one's name
+    let mut written = Vec::new();
+    let mut renamed = Vec::new();
+    for note in notes {
+        let path = dir.join(format!("{}.md", note.name));
+        let previous = match note.subject {
+            Some(subject) => existing.get(&subject).cloned(),
+            None => Some(path.clone()),
+        };
+        let user_notes = previous
+            .as_ref()
+            .and_then(|previous| fs::read_to_string(previous).ok())
+            .and_then(|existing| {
+                existing
+                    .split_once(USER_NOTES_MARKER)
+                    .map(|(_, kept)| kept.to_string())
+            })
+            .unwrap_or_else(|| String::from("\n\n## Notes\n\n"));
+        renamed.extend(previous.filter(|previous| *previous != path));
+
+        let content = format!(
+            "{}\n\n{}{}",
+            note.content.trim_end(),
+            USER_NOTES_MARKER,
+            user_notes
+        );
+        written.push((path, content));
+    }
+
+    for (path, content) in &written {
+        fs::write(path, content)?;
+    }
+    for path in renamed {
+        if !written.iter().any(|(written, _)| *written == path) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn note_subject(content: &str) -> Option<NoteSubject> {
+    let (yaml, _) = content.strip_prefix("---\n")?.split_once("\n---\n")?;
+    let ids: StoredIds = serde_yaml::from_str(yaml).ok()?;
+    match (ids.module_id, ids.section_id) {
+        (Some(id), _) => Some(NoteSubject::Module(id)),
+        (None, Some(id)) => Some(NoteSubject::Section(id)),
+        (None, None) => None,
+    }
+}
+
+fn with_front_matter(front_matter: &FrontMatter, body: &str) -> String {
+    let yaml = serde_yaml::to_string(front_matter).unwrap_or_default();
+    format!("---\n{}---\n\n{}", yaml, body)
+}
+
+fn module_url(course: &VaultCourse, modname: &str, id: i64) -> String {
+    if modname == "module" {
+        format!(
+            "https://{}/course/view.php?id={}",
+            course.base_url, course.id
+        )
+    } else {
+        format!(
+            "https://{}/mod/{}/view.php?id={}",
+            course.base_url, modname, id
+        )
+    }
+}
+
+fn wiki_link(target: &str, label: &str) -> String {
+    let label = label.replace(['[', ']', '|'], " ");
+    if label == target {
+        format!("[[{}]]", target)
+    } else {
+        format!("[[{}|{}]]", target, label.trim())
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Obsidian refuses these in file names, and they break wiki-links
+fn note_name(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    replaced.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn section_title(section: &CourseSection) -> String {
+    if section.name.trim().is_empty() {
+        format!("Section {}", section.id)
+    } else {
+        section.name.clone()
+    }
+}
+
+// Note names more than one section or module would get. Labels get no note,
+// and the course index keeps its name.
+fn shared_names(course: &VaultCourse, sections: &[CourseSection]) -> HashSet<String> {
+    let mut seen = HashSet::from([note_name(course.name)]);
+    let mut shared = HashSet::new();
+    let titles = sections.iter().flat_map(|section| {
+        let modules = section
+            .modules
+            .iter()
+            .filter(|module| module.modname.as_deref() != Some("label"))
+            .map(|module| module.name.clone());
+        std::iter::once(section_title(section)).chain(modules)
+    });
+    for title in titles {
+        let name = note_name(&format!("{} - {}", course.name, title));
+        if !seen.insert(name.clone()) {
+            shared.insert(name);
+        }
+    }
+    shared
+}
+
+// Modules often share names across sections, each of those gets its id
+// appended so a name doesn't depend on the order of the course
+fn unique_name(shared: &HashSet<String>, name: &str, id: i64) -> String {
+    let name = note_name(name);
+    if shared.contains(&name) {
+        format!("{} ({})", name, id)
+    } else {
+        name
+    }
+}
+
+fn tag(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::course::CourseModule;
+
+    const COURSE: VaultCourse = VaultCourse {
+        id: 1,
+        name: "Course",
+        base_url: "moodle.example.com",
+    };
+
+    fn section(id: i64, name: &str, modules: &[(i64, &str)]) -> CourseSection {
+        CourseSection {
+            id,
+            name: name.to_string(),
+            summary: String::new(),
+            courseid: Some(1),
+            modules: modules
+                .iter()
+                .map(|(id, name)| CourseModule {
+                    id: *id,
+                    name: name.to_string(),
+                    modname: Some(String::from("resource")),
+                    instance: None,
+                    contextid: None,
+                    description: None,
+                    contents: None,
+                    section_id: None,
+                    lastfetched: None,
+                })
+                .collect(),
+            lastfetched: None,
+        }
+    }
+
+    fn names(sections: Vec<CourseSection>) -> Vec<String> {
+        let mut names: Vec<String> = parse_vault(&COURSE, sections, &[], &[], &HashMap::new())
+            .into_iter()
+            .map(|note| note.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn names_do_not_depend_on_course_order() {
+        let week1 = || section(1, "Week 1", &[(10, "Slides"), (11, "Quiz")]);
+        let week2 = || section(2, "Week 2", &[(20, "Slides")]);
+        let expected = [
+            "Course",
+            "Course - Quiz",
+            "Course - Slides (10)",
+            "Course - Slides (20)",
+            "Course - Week 1",
+            "Course - Week 2",
+        ];
+        assert_eq!(names(vec![week1(), week2()]), expected);
+        assert_eq!(names(vec![week2(), week1()]), expected);
+    }
+
+    #[test]
+    fn keeps_user_notes_of_renamed_notes() {
+        let dir = std::env::temp_dir().join(format!("moodl-rs-vault-{}", std::process::id()));
+        let notes = |sections| parse_vault(&COURSE, sections, &[], &[], &HashMap::new());
+
+        save_notes(notes(vec![section(2, "Week 2", &[(20, "Slides")])]), &dir).unwrap();
+        let old_path = dir.join("Course - Slides.md");
+        let written = fs::read_to_string(&old_path).unwrap();
+        fs::write(&old_path, format!("{}Read these twice\n", written)).unwrap();
+
+        // A module of the same name earlier in the course renames the note
+        let sections = vec![
+            section(1, "Week 1", &[(10, "Slides")]),
+            section(2, "Week 2", &[(20, "Slides")]),
+        ];
+        save_notes(notes(sections), &dir).unwrap();
+        let moved = fs::read_to_string(dir.join("Course - Slides (20).md"));
+        let added = fs::read_to_string(dir.join("Course - Slides (10).md"));
+        let old_exists = old_path.exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(moved.unwrap().ends_with("Read these twice\n"));
+        assert!(!added.unwrap().contains("Read these twice"));
+        assert!(!old_exists);
+    }
+}
@@ -0,0 +1,171 @@
+// ui/cli.rs
+//
+use {
+    crate::{ui::prompt::UserCommand, utils::parse_duration},
+    clap::{Parser, Subcommand},
+    std::{path::PathBuf, time::Duration},
+};
+
+/// Fetch, download and parse Moodle course material.
+/// Run without a command to choose one interactively.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+    /// Never reach Moodle, commands only work with what is already stored
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Profile from 'config.toml' to use instead of the default site and account
+    #[arg(long, global = true)]
+    profile: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Initialize user information
+    Init,
+    /// Fetch course material into the database
+    Fetch {
+        /// Refetch every course in full instead of only what changed since the last fetch
+        #[arg(long)]
+        full: bool,
+        /// List the requests a fetch would make without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Download all course materials
+    Download {
+        /// List what would be downloaded and where without downloading it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Parse course pages to markdown
+    Parse,
+    /// Run fetch, download and parse sequentially
+    Sync {
+        /// Refetch every course in full instead of only what changed since the last fetch
+        #[arg(long)]
+        full: bool,
+        /// Sync the default profile and then every other one
+        #[arg(long, conflicts_with = "profile")]
+        all_profiles: bool,
+    },
+    /// Sync on a schedule until interrupted
+    Watch {
+        /// Time between the start of one sync and the next, like 90s, 30m, 2h or 1d
+        #[arg(long, default_value = "30m", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Show stored grades of the tracked courses
+    Grades {
+        /// Id of a tracked course, all courses when omitted
+        #[arg(long)]
+        course: Option<i64>,
+        /// Show every value each grade item had since it was first fetched
+        #[arg(long, conflicts_with = "what_if")]
+        history: bool,
+        /// Enter hypothetical grades for ungraded items to project the course total
+        #[arg(long)]
+        what_if: bool,
+        /// One line per course with its total, rank and latest grade
+        #[arg(long, conflicts_with_all = ["course", "history", "what_if"])]
+        overview: bool,
+    },
+    /// List tracked activities that aren't completed yet
+    Todo {
+        /// Id of a tracked course, all courses when omitted
+        #[arg(long)]
+        course: Option<i64>,
+    },
+    /// List what the last fetches added, removed or modified
+    Changes {
+        /// Id of a tracked course, all courses when omitted
+        #[arg(long)]
+        course: Option<i64>,
+        /// Number of fetches to go back
+        #[arg(long, default_value_t = 1)]
+        last: usize,
+    },
+    /// Export tracked courses from the database
+    Export {
+        #[command(subcommand)]
+        target: ExportTarget,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExportTarget {
+    /// Static HTML site with an index page and search across courses
+    Html {
+        /// Directory to write the site to, defaults to the data directory
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// EPUB 3 book of one course, with a chapter per section
+    Epub {
+        /// Id of a tracked course
+        #[arg(long)]
+        course: i64,
+        /// File to write the book to, defaults to `<shortname>.epub` next to the course markdown
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Total, rank and latest grade of every tracked course as a markdown table
+    Grades {
+        /// Write JSON instead of markdown
+        #[arg(long)]
+        json: bool,
+        /// File to write to, defaults to standard output
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Course tree, assignments and grades as JSON, see docs/export-schema.org
+    Json {
+        /// Id of a tracked course, all tracked courses when omitted
+        #[arg(long)]
+        course: Option<i64>,
+        /// Write YAML instead of JSON
+        #[arg(long)]
+        yaml: bool,
+        /// File to write to, defaults to standard output
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// The command given on the command line, if any
+    pub fn user_command(self) -> Option<UserCommand> {
+        self.command.map(|command| match command {
+            CliCommand::Init => UserCommand::Init,
+            CliCommand::Fetch { full, dry_run } => UserCommand::Fetch { full, dry_run },
+            CliCommand::Download { dry_run } => UserCommand::Download { dry_run },
+            CliCommand::Parse => UserCommand::Parse,
+            CliCommand::Sync { full, all_profiles } => UserCommand::Default { full, all_profiles },
+            CliCommand::Watch { interval } => UserCommand::Watch { interval },
+            CliCommand::Grades {
+                course,
+                history,
+                what_if,
+                overview,
+            } => UserCommand::Grades {
+                course,
+                history,
+                what_if,
+                overview,
+            },
+            CliCommand::Todo { course } => UserCommand::Todo { course },
+            CliCommand::Changes { course, last } => UserCommand::Changes { course, last },
+            CliCommand::Export { target } => UserCommand::Export(target),
+        })
+    }
+}
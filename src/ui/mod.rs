@@ -1,2 +1,4 @@
-pub mod tui;
+pub mod cli;
 pub mod prompt;
+pub mod table;
+pub mod tui;
@@ -2,17 +2,46 @@
 //
 
 use {
-    crate::models::{configs::*, courses::*},
+    crate::{
+        models::{configs::*, courses::*},
+        ui::cli::ExportTarget,
+    },
     eyre::Result,
+    std::time::Duration,
     termimad::{MadSkin, Question},
 };
 
 pub enum UserCommand {
     Init,
-    Fetch,
+    Fetch {
+        full: bool,
+        dry_run: bool,
+    },
     Parse,
-    Download,
-    Default,
+    Download {
+        dry_run: bool,
+    },
+    Default {
+        full: bool,
+        all_profiles: bool,
+    },
+    Watch {
+        interval: Duration,
+    },
+    Grades {
+        course: Option<i64>,
+        history: bool,
+        what_if: bool,
+        overview: bool,
+    },
+    Changes {
+        course: Option<i64>,
+        last: usize,
+    },
+    Todo {
+        course: Option<i64>,
+    },
+    Export(ExportTarget),
 }
 
 pub fn prompt_command(skin: &MadSkin) -> Result<UserCommand> {
@@ -44,14 +73,20 @@ pub fn prompt_command(skin: &MadSkin) -> Result<UserCommand> {
 
     match a.as_str() {
         "i" => Ok(UserCommand::Init),
-        "f" => Ok(UserCommand::Fetch),
-        "D" => Ok(UserCommand::Download),
+        "f" => Ok(UserCommand::Fetch {
+            full: false,
+            dry_run: false,
+        }),
+        "D" => Ok(UserCommand::Download { dry_run: false }),
         "p" => Ok(UserCommand::Parse),
-        _ => Ok(UserCommand::Default),
+        _ => Ok(UserCommand::Default {
+            full: false,
+            all_profiles: false,
+        }),
     }
 }
 
-pub fn prompt_courses(courses: &Vec<Course>, skin: &MadSkin) -> Result<Vec<CourseConfig>> {
+pub fn prompt_courses(courses: &[Course], skin: &MadSkin) -> Result<Vec<CourseConfig>> {
     let mut selected_courses = Vec::new();
 
     for course in courses.iter() {
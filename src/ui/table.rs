@@ -0,0 +1,32 @@
+// ui/table.rs
+//
+
+/// Prints `rows` under `header` with aligned columns, or `empty` when there
+/// are no rows
+pub fn print_table<const N: usize>(header: [&str; N], rows: Vec<[String; N]>, empty: &str) {
+    if rows.is_empty() {
+        println!("  {}\n", empty);
+        return;
+    }
+
+    let mut widths = header.map(|title| title.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: [&str; N]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("  {}", padded.join("  ").trim_end());
+    };
+    line(header);
+    for row in &rows {
+        line(row.each_ref().map(String::as_str));
+    }
+    println!();
+}
@@ -3,12 +3,17 @@
 use eyre::Result;
 use fern::InitError;
 use std::{
-    fs,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
     path::{Path, PathBuf},
+    sync::{OnceLock, RwLock},
+    time::Duration,
 };
 use {
-    chrono::Local,
+    chrono::{Local, TimeZone},
+    scraper::Html,
     termimad::{crossterm::style::Color::*, MadSkin, *},
+    unicode_properties::emoji::{self, EmojiStatus, UnicodeEmoji},
 };
 
 #[cfg(not(target_os = "windows"))]
@@ -46,6 +51,107 @@ pub fn create_dir(file_path: &str) -> Result<()> {
     Ok(())
 }
 
+/// Held while a sync runs so two never overlap, released when dropped
+pub struct SyncLock {
+    path: PathBuf,
+}
+
+impl SyncLock {
+    /// Takes the lock file next to the database, none while a running
+    /// process holds it. Locks left behind by a process that died are taken over.
+    pub fn acquire() -> Result<Option<Self>> {
+        let path = Self::path();
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(Some(SyncLock { path }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let pid = fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|pid| pid.trim().parse::<u32>().ok());
+                    match pid {
+                        Some(pid) if !is_running(pid) => {
+                            log::warn!("Taking over the lock of process {}, it stopped", pid);
+                            fs::remove_file(&path)?;
+                        }
+                        _ => return Ok(None),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Where the lock file is kept, one per database so profiles sync independently
+    pub fn path() -> PathBuf {
+        crate::db::db_path().with_extension("lock")
+    }
+}
+
+impl Drop for SyncLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::error!("Failed to remove '{}': {:?}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+// Without a cheap way to tell, the lock file has to be removed by hand
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+/// Parses durations like `90s`, `30m`, `2h` or `1d`
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' doesn't start with a number", text))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("'{}' needs a unit of s, m, h or d", text)),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("'{}' is too long", text))
+}
+
+/// Path of `target` relative to `base_dir`, with `/` separators for use in links
+pub fn relative_path(base_dir: &Path, target: &Path) -> String {
+    let base: Vec<_> = base_dir.components().collect();
+    let target: Vec<_> = target.components().collect();
+    let common = base
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        target[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
+
 pub fn modify_shortname(shortname: &str) -> String {
     let re = regex::Regex::new(r"(?i)([a-z]+)\s*(\d+)(?:\s*(lab|l))?").unwrap();
     if let Some(caps) = re.captures(shortname) {
@@ -58,6 +164,58 @@ pub fn modify_shortname(shortname: &str) -> String {
     shortname.to_string()
 }
 
+/// Removes emoji, keeping every other character including symbols
+/// like © or ™ that only render as emoji when explicitly requested
+pub fn strip_emojis(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut stripped = String::with_capacity(text.len());
+    let mut previous_removed = false;
+
+    for (i, &c) in chars.iter().enumerate() {
+        let default_emoji = matches!(
+            c.emoji_status(),
+            EmojiStatus::EmojiPresentation
+                | EmojiStatus::EmojiPresentationAndModifierBase
+                | EmojiStatus::EmojiPresentationAndEmojiComponent
+                | EmojiStatus::EmojiPresentationAndModifierAndEmojiComponent
+        );
+        let requested_emoji = !c.is_ascii()
+            && c.is_emoji_char()
+            && chars
+                .get(i + 1)
+                .is_some_and(|&next| emoji::is_emoji_presentation_selector(next));
+        let sequence_part = emoji::is_emoji_presentation_selector(c)
+            || emoji::is_tag_character(c)
+            || c == '\u{20E3}'
+            || (emoji::is_zwj(c) && previous_removed);
+
+        previous_removed = default_emoji || requested_emoji || sequence_part;
+        if !previous_removed {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Local date and time of a Unix timestamp, N/A for Moodle's 0 for not set
+pub fn format_date(timestamp: i64) -> String {
+    if timestamp <= 0 {
+        return String::from("N/A");
+    }
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| String::from("N/A"))
+}
+
+/// Text of an HTML fragment with its whitespace collapsed
+pub fn plain_text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let text: String = fragment.root_element().text().collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 pub fn make_skin() -> MadSkin {
     let mut skin = MadSkin::default();
     skin.table.align = Alignment::Center;
@@ -68,6 +226,30 @@ pub fn make_skin() -> MadSkin {
     skin
 }
 
+// Tokens in use, see `add_secret`
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Has `redact` hide `secret` wherever it shows up, not only in URLs
+pub fn add_secret(secret: &str) {
+    let mut secrets = SECRETS.write().unwrap();
+    if !secret.is_empty() && !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Hides `token` and `wstoken` query parameters and known secrets in `text`
+pub fn redact(text: &str) -> String {
+    static TOKEN_PARAMETER: OnceLock<regex::Regex> = OnceLock::new();
+    let re = TOKEN_PARAMETER
+        .get_or_init(|| regex::Regex::new(r#"\b((?:ws)?token=)[^&\s)'"]+"#).unwrap());
+
+    let mut redacted = re.replace_all(text, "${1}<redacted>").into_owned();
+    for secret in SECRETS.read().unwrap().iter() {
+        redacted = redacted.replace(secret.as_str(), "<redacted>");
+    }
+    redacted
+}
+
 pub fn setup_logger() -> Result<(), InitError> {
     fern::Dispatch::new()
         .format(|out, message, record| {
@@ -76,14 +258,48 @@ pub fn setup_logger() -> Result<(), InitError> {
                 Local::now().format("%H:%M:%S"),
                 record.target(),
                 record.level(),
-                message
+                redact(&message.to_string())
             ))
         })
         // .level(log::LevelFilter::Debug)
         .level(log::LevelFilter::Info)
         .level_for("html5ever", log::LevelFilter::Warn)
         .level_for("selectors", log::LevelFilter::Warn)
-        .chain(std::io::stdout())
+        .chain(std::io::stderr())
         .apply()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert!(parse_duration("300000000000000d").is_err());
+    }
+
+    #[test]
+    fn redacts_tokens_from_urls_and_text() {
+        assert_eq!(
+            redact("https://moodle.example/webservice/pluginfile.php/1/a.pdf?forcedownload=1&token=abc123"),
+            "https://moodle.example/webservice/pluginfile.php/1/a.pdf?forcedownload=1&token=<redacted>"
+        );
+        assert_eq!(
+            redact("GET server.php?wstoken=abc123&wsfunction=x failed"),
+            "GET server.php?wstoken=<redacted>&wsfunction=x failed"
+        );
+        add_secret("0123456789abcdef");
+        assert_eq!(
+            redact("token: \"0123456789abcdef\""),
+            "token: \"<redacted>\""
+        );
+    }
+}
@@ -1,24 +1,39 @@
 // ws.rs
 //
 use crate::models::{
-    assignments::Assignments, configs::Configs, course::CourseSection, course::Pages,
-    courses::Course, grades::CourseGrades, scorm::Scorms, user::SiteInfo,
+    assignments::Assignments,
+    completion::{ActivitiesCompletion, CourseCompletionResponse},
+    configs::Configs,
+    course::CourseSection,
+    course::CourseUpdates,
+    course::Pages,
+    courses::Course,
+    grades::CourseGrades,
+    grades::CourseTotal,
+    scorm::Scorms,
+    urls::Urls,
+    user::SiteInfo,
 };
+use crate::utils::{add_secret, redact};
 use eyre::Result;
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest;
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use std::{cmp::min, fs::metadata, fs::File, io::Write, path::Path};
-use log::{info, debug};
 
-const GET_ASSIGNMENTS: &str = "mod_assign_get_assignments";
-const GET_CONTENTS: &str = "core_course_get_contents";
-const GET_COURSES: &str = "core_enrol_get_users_courses";
-const GET_GRADES: &str = "gradereport_user_get_grade_items";
-const GET_PAGES: &str = "mod_page_get_pages_by_courses";
-const GET_SCORM: &str = "mod_scorm_get_scorms_by_courses";
-const GET_UID: &str = "core_webservice_get_site_info";
+pub const GET_ASSIGNMENTS: &str = "mod_assign_get_assignments";
+pub const GET_ACTIVITIES_COMPLETION: &str = "core_completion_get_activities_completion_status";
+pub const GET_COURSE_COMPLETION: &str = "core_completion_get_course_completion_status";
+pub const GET_CONTENTS: &str = "core_course_get_contents";
+pub const GET_COURSES: &str = "core_enrol_get_users_courses";
+pub const GET_GRADES: &str = "gradereport_user_get_grade_items";
+pub const GET_GRADE_OVERVIEW: &str = "gradereport_overview_get_course_grades";
+pub const GET_PAGES: &str = "mod_page_get_pages_by_courses";
+pub const GET_SCORM: &str = "mod_scorm_get_scorms_by_courses";
+pub const GET_UID: &str = "core_webservice_get_site_info";
+pub const GET_UPDATES: &str = "core_course_get_updates_since";
+pub const GET_URLS: &str = "mod_url_get_urls_by_courses";
 
 #[derive(Clone)]
 pub struct ApiClient {
@@ -26,6 +41,7 @@ pub struct ApiClient {
     client: reqwest::Client,
     wstoken: String,
     userid: i64,
+    allowed_hosts: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,7 +54,12 @@ pub enum ApiResponse {
     Scorms(Scorms),
     UserGrades(UserGradesResponse),
     Pages(Pages),
+    Urls(Urls),
     Assignments(Assignments),
+    GradeOverview(GradeOverviewResponse),
+    ActivitiesCompletion(ActivitiesCompletion),
+    CourseCompletion(CourseCompletionResponse),
+    Updates(CourseUpdates),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,6 +67,11 @@ pub struct UserGradesResponse {
     pub usergrades: Vec<CourseGrades>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GradeOverviewResponse {
+    pub grades: Vec<CourseTotal>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiError {
     exception: String,
@@ -59,6 +85,11 @@ pub struct QueryParameters<'a> {
     wsfunction: Option<String>,
     courseid: Option<i64>,
     userid: Option<i64>,
+    since: Option<i64>,
+    #[serde(rename = "options[0][name]")]
+    option_name: Option<String>,
+    #[serde(rename = "options[0][value]")]
+    option_value: Option<String>,
     moodlewsrestformat: String,
     wstoken: String,
     #[serde(skip)]
@@ -72,6 +103,9 @@ impl<'a> QueryParameters<'a> {
             wsfunction: None,
             courseid: None,
             userid: None,
+            since: None,
+            option_name: None,
+            option_value: None,
             moodlewsrestformat: "json".to_string(),
             wstoken: "".to_string(),
             client,
@@ -88,6 +122,18 @@ impl<'a> QueryParameters<'a> {
         self
     }
 
+    pub fn since(mut self, since: i64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// A single `options` entry, as `core_course_get_contents` takes them
+    pub fn option(mut self, name: &str, value: impl ToString) -> Self {
+        self.option_name = Some(name.to_string());
+        self.option_value = Some(value.to_string());
+        self
+    }
+
     pub fn use_default_userid(mut self) -> Self {
         self.userid = Some(self.client.userid);
         self
@@ -101,6 +147,7 @@ impl<'a> QueryParameters<'a> {
 
 pub trait ApiQuery: Serialize {
     fn with_token(self, token: &str) -> Self;
+    #[allow(dead_code)]
     fn with_userid(self, userid: Option<i64>) -> Self;
 }
 
@@ -119,27 +166,38 @@ impl ApiQuery for QueryParameters<'_> {
 impl ApiClient {
     pub fn new(base_url: &str, token: &str, userid: &i64) -> Self {
         debug!("New API Client created");
+        add_secret(token);
         ApiClient {
             base_url: base_url.to_string(),
             wstoken: token.to_string(),
             client: reqwest::Client::new(),
-            userid: userid.clone(),
+            userid: *userid,
+            allowed_hosts: Vec::new(),
         }
     }
 
+    pub fn allowed_hosts(mut self, hosts: &[String]) -> Self {
+        self.allowed_hosts = hosts.to_vec();
+        self
+    }
+
     pub fn from_config(configs: &Configs) -> Result<Self> {
+        let token = configs.token().ok_or_else(|| {
+            eyre::eyre!(
+                "No API token, run init or set {}",
+                crate::credentials::env_var(configs.profile.as_deref())
+            )
+        })?;
+        let client = ApiClient::new(&configs.api.base_url, token, &configs.api.userid)
+            .allowed_hosts(&configs.links.allowed_hosts);
         debug!(
-            "Using API config from file\napi - base_url: {:?} \napi - token: {:?}\napi - userid: {:?}",
+            "Using API config from file\napi - base_url: {:?} \napi - token: {}\napi - userid: {:?}",
             configs.api.base_url,
-            configs.api.token,
+            redact(token),
             configs.api.userid,
         );
 
-        Ok(ApiClient::new(
-            &configs.api.base_url,
-            &configs.api.token,
-            &configs.api.userid,
-        ))
+        Ok(client)
     }
 
     pub async fn fetch<T: ApiQuery>(&self, query: T) -> Result<ApiResponse> {
@@ -149,9 +207,10 @@ impl ApiClient {
             .get(base_url)
             .query(&query.with_token(&self.wstoken))
             .send()
-            .await?;
+            .await
+            .map_err(reqwest::Error::without_url)?;
 
-        let response_text = response.text().await?;
+        let response_text = response.text().await.map_err(reqwest::Error::without_url)?;
         debug!("API Response: {}", &response_text);
 
         // First, try to parse the response as an ApiError
@@ -170,13 +229,41 @@ impl ApiClient {
         }
     }
 
+    /// Whether `url` points at the configured Moodle site
+    pub fn is_moodle_url(&self, url: &str) -> bool {
+        is_moodle_url(&self.base_url, url)
+    }
+
+    /// Whether `url` points at a host listed in `allowed_hosts`, subdomains included
+    pub fn is_allowed_url(&self, url: &str) -> bool {
+        is_allowed_url(&self.allowed_hosts, url)
+    }
+
+    // Tokens are only accepted by the webservice variant of pluginfile.php,
+    // links embedded in descriptions may point at the browser one
+    fn url_with_token(&self, url: &str) -> Result<String> {
+        let mut parsed = reqwest::Url::parse(url)?;
+        if !parsed.path().contains("/webservice/pluginfile.php/") {
+            let path = parsed
+                .path()
+                .replacen("/pluginfile.php/", "/webservice/pluginfile.php/", 1);
+            parsed.set_path(&path);
+        }
+        parsed.query_pairs_mut().append_pair("token", &self.wstoken);
+        Ok(parsed.to_string())
+    }
+
     pub async fn download_file(&self, url: &str, file_path: &str) -> Result<(), eyre::Report> {
-        let url_with_token;
-        if url.contains(&self.base_url) {
-            url_with_token = format!("{}&token={}", url, self.wstoken);
+        let url_with_token = if self.is_moodle_url(url) {
+            self.url_with_token(url)?
+        } else if self.is_allowed_url(url) {
+            url.to_string()
         } else {
-            url_with_token = url.to_string();
-        }
+            return Err(eyre::eyre!(
+                "Refusing to download '{}', its host is not in 'allowed_hosts'",
+                url
+            ));
+        };
 
         let res = self
             .client
@@ -202,9 +289,7 @@ impl ApiClient {
                         );
                         info!("File '{}' already downloaded and has the same size. Skipping download.", short_path);
                     } else {
-                        info!(
-                            "File already downloaded and has the same size. Skipping download."
-                        );
+                        info!("File already downloaded and has the same size. Skipping download.");
                     }
                 } else {
                     info!("File already downloaded and has the same size. Skipping download.");
@@ -255,6 +340,35 @@ impl ApiClient {
         self.fetch(query).await
     }
 
+    /// Sections and modules of the course without the files of its modules
+    pub async fn fetch_course_outline(&self, course_id: i64) -> Result<ApiResponse> {
+        info!("Fetching course {} outline", course_id);
+        let query = QueryParameters::new(self)
+            .function(GET_CONTENTS)
+            .courseid(course_id)
+            .option("excludecontents", 1);
+        self.fetch(query).await
+    }
+
+    /// Contents of a single module, within its section
+    pub async fn fetch_course_module(&self, course_id: i64, cmid: i64) -> Result<ApiResponse> {
+        info!("Fetching course {} module {}", course_id, cmid);
+        let query = QueryParameters::new(self)
+            .function(GET_CONTENTS)
+            .courseid(course_id)
+            .option("cmid", cmid);
+        self.fetch(query).await
+    }
+
+    pub async fn fetch_course_updates(&self, course_id: i64, since: i64) -> Result<ApiResponse> {
+        info!("Fetching course {} updates since {}", course_id, since);
+        let query = QueryParameters::new(self)
+            .function(GET_UPDATES)
+            .courseid(course_id)
+            .since(since);
+        self.fetch(query).await
+    }
+
     pub async fn fetch_course_grades(&self, course_id: i64) -> Result<ApiResponse> {
         info!("Fetching course {} grades", course_id);
         let query = QueryParameters::new(self)
@@ -264,6 +378,32 @@ impl ApiClient {
         self.fetch(query).await
     }
 
+    pub async fn fetch_grade_overview(&self) -> Result<ApiResponse> {
+        info!("Fetching grade overview");
+        let query = QueryParameters::new(self)
+            .function(GET_GRADE_OVERVIEW)
+            .use_default_userid();
+        self.fetch(query).await
+    }
+
+    pub async fn fetch_activities_completion(&self, course_id: i64) -> Result<ApiResponse> {
+        info!("Fetching course {} activity completion", course_id);
+        let query = QueryParameters::new(self)
+            .function(GET_ACTIVITIES_COMPLETION)
+            .courseid(course_id)
+            .use_default_userid();
+        self.fetch(query).await
+    }
+
+    pub async fn fetch_course_completion(&self, course_id: i64) -> Result<ApiResponse> {
+        info!("Fetching course {} completion", course_id);
+        let query = QueryParameters::new(self)
+            .function(GET_COURSE_COMPLETION)
+            .courseid(course_id)
+            .use_default_userid();
+        self.fetch(query).await
+    }
+
     pub async fn fetch_course_pages(&self) -> Result<ApiResponse> {
         info!("Fetching course pages");
         let query = QueryParameters::new(self).function(GET_PAGES);
@@ -290,9 +430,51 @@ impl ApiClient {
         self.fetch(query).await
     }
 
+    pub async fn fetch_urls(&self) -> Result<ApiResponse> {
+        info!("Fetching url modules");
+        let query = QueryParameters::new(self).function(GET_URLS);
+        self.fetch(query).await
+    }
+
     pub async fn fetch_user_id(&self) -> Result<ApiResponse> {
         info!("Fetching user id");
         let query = QueryParameters::new(self).function(GET_UID);
         self.fetch(query).await
     }
 }
+
+/// Whether `url` points at the Moodle site at `base_url`
+pub fn is_moodle_url(base_url: &str, url: &str) -> bool {
+    let site_host = base_url.split('/').next().unwrap_or_default();
+    url_host(url).is_some_and(|host| host.eq_ignore_ascii_case(site_host))
+}
+
+/// Whether `url` points at one of `allowed_hosts`, subdomains included. Host
+/// names are compared ignoring case.
+pub fn is_allowed_url(allowed_hosts: &[String], url: &str) -> bool {
+    url_host(url).is_some_and(|host| {
+        allowed_hosts.iter().any(|allowed| {
+            let allowed = allowed.to_lowercase();
+            host == allowed || host.ends_with(&format!(".{}", allowed))
+        })
+    })
+}
+
+fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(|host| host.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_hosts_ignore_case() {
+        let allowed = vec![String::from("Example.org")];
+        assert!(is_allowed_url(&allowed, "https://example.org/a.pdf"));
+        assert!(is_allowed_url(&allowed, "https://CDN.EXAMPLE.ORG/a.pdf"));
+        assert!(!is_allowed_url(&allowed, "https://notexample.org/a.pdf"));
+    }
+}